@@ -0,0 +1,105 @@
+//! Compares presenting the easy6502 screen by rescanning all 1024 cells
+//! after every instruction against the dirty rows the bus watcher
+//! tracks, both running the snake game on the real machine.
+//!
+//! Run with `cargo bench --bench framebuffer`.
+
+use std::cell::RefCell;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use sens::easy6502::{self, Easy6502, HEIGHT, WIDTH};
+use sens::framebuffer::Framebuffer;
+use sens::random::Xorshift;
+use sens::scheduler::Clock;
+use sens::{StopReason, CPU};
+
+const FRAMES: u64 = 120;
+const SCREEN: u16 = 0x0200;
+/// Turns the snake every so often so that it keeps moving around.
+const KEYS: [u8; 4] = [b'd', b's', b'a', b'w'];
+
+fn boot() -> (CPU, Easy6502) {
+    let mut cpu = CPU::new();
+    let machine = Easy6502::attach(&mut cpu, Box::new(Xorshift::new(1)));
+    cpu.load(easy6502::SNAKE.to_vec()).expect("snake fits in memory");
+    cpu.reset();
+    (cpu, machine)
+}
+
+/// Runs `FRAMES` frames of snake, calling `after_instruction` after each
+/// instruction and `present` at the end of each frame. The game starts
+/// over when the snake dies.
+fn play<I, P>(mut after_instruction: I, mut present: P) -> Duration
+where
+    I: FnMut(&CPU),
+    P: FnMut(&Easy6502),
+{
+    let (mut cpu, mut machine) = boot();
+    let frame_cycles = Clock::EASY6502.cycles_per_frame() as u64;
+    let start = Instant::now();
+    for frame in 0..FRAMES {
+        if frame % 10 == 0 {
+            machine.key_down(KEYS[(frame / 10) as usize % KEYS.len()]);
+        }
+        let target = cpu.cycles() + frame_cycles;
+        let reason = cpu.run_while(|cpu| {
+            after_instruction(cpu);
+            (cpu.cycles() >= target).then_some(StopReason::BudgetExhausted)
+        });
+        present(&machine);
+        if reason != StopReason::BudgetExhausted {
+            (cpu, machine) = boot();
+        }
+    }
+    start.elapsed()
+}
+
+fn rescan_every_instruction() -> Duration {
+    // both callbacks need the screen, one at a time
+    let fb = RefCell::new(Framebuffer::new(WIDTH, HEIGHT));
+    let mut uploaded = 0;
+    let time = play(
+        |cpu| {
+            let mut fb = fb.borrow_mut();
+            for cell in 0..WIDTH * HEIGHT {
+                let color = easy6502::color(cpu.mem_peek(SCREEN + cell as u16));
+                fb.set_pixel(cell % WIDTH, cell / WIDTH, color);
+            }
+        },
+        |_| {
+            // any change meant uploading the whole texture
+            let mut fb = fb.borrow_mut();
+            if fb.take_dirty_rows().is_some() {
+                uploaded += black_box(fb.pixels()).len();
+            }
+        },
+    );
+    black_box(uploaded);
+    time
+}
+
+fn dirty_tracking() -> Duration {
+    let mut uploaded = 0;
+    let time = play(
+        |_| {},
+        |machine| {
+            let mut screen = machine.screen();
+            if let Some(rows) = screen.take_dirty_rows() {
+                let pitch = screen.pitch();
+                uploaded += black_box(&screen.pixels()[rows.start * pitch..rows.end * pitch]).len();
+            }
+        },
+    );
+    black_box(uploaded);
+    time
+}
+
+fn main() {
+    let rescan = rescan_every_instruction();
+    let dirty = dirty_tracking();
+    let per_frame = |time: Duration| time / FRAMES as u32;
+    println!("rescan every instruction: {:>12?} per frame", per_frame(rescan));
+    println!("dirty tracking:           {:>12?} per frame", per_frame(dirty));
+    println!("speed-up:                 {:>11.0}x", rescan.as_secs_f64() / dirty.as_secs_f64().max(1e-9));
+}
@@ -0,0 +1,300 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::loader::{Image, Segment};
+use crate::opcodes::{self, OpCode};
+use crate::AddressingMode;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+enum Item {
+    Bytes(Vec<String>),
+    Instruction { op: &'static OpCode, operand: String },
+}
+
+struct Statement {
+    line: usize,
+    addr: u16,
+    item: Item,
+}
+
+/// Assembles easy6502-style source: `label:` definitions, `define NAME
+/// value`, `*=` / `.org` to move the program counter, `dcb` / `.byte`
+/// data, `$hex`, `%binary` and decimal numbers, `<` / `>` to take the
+/// low or high byte and `+` / `-` offsets. Code starts at `origin`.
+pub fn assemble(source: &str, origin: u16) -> Result<Image, AsmError> {
+    let mut symbols: BTreeMap<String, u16> = BTreeMap::new();
+    let mut statements = Vec::new();
+    let mut pc = origin as u32;
+
+    // first pass: addresses and instruction sizes
+    for (i, line) in source.lines().enumerate() {
+        let line_no = i + 1;
+        let error = |message: String| AsmError { line: line_no, message };
+        let mut text = line.split(';').next().unwrap_or("").trim();
+
+        if let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            if is_identifier(label) {
+                if symbols.insert(label.to_string(), pc as u16).is_some() {
+                    return Err(error(format!("label {} defined twice", label)));
+                }
+                text = rest.trim();
+            }
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let (word, rest) = match text.split_once(char::is_whitespace) {
+            Some((word, rest)) => (word, rest.trim()),
+            None => (text, ""),
+        };
+        let keyword = word.to_ascii_lowercase();
+        if keyword == "define" {
+            let (name, value) = rest
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| error("define needs a name and a value".to_string()))?;
+            let value = evaluate(value.trim(), &symbols)
+                .map_err(error)?
+                .ok_or_else(|| error(format!("cannot define {} from an undefined symbol", name)))?;
+            symbols.insert(name.to_string(), value);
+        } else if let Some(addr) = text.strip_prefix("*=").or_else(|| (keyword == ".org").then_some(rest)) {
+            let addr = evaluate(addr.trim(), &symbols)
+                .map_err(error)?
+                .ok_or_else(|| error("origin must be known on first use".to_string()))?;
+            pc = addr as u32;
+        } else if matches!(keyword.as_str(), "dcb" | ".byte" | ".db") {
+            let values: Vec<String> = rest.split(',').map(|value| value.trim().to_string()).collect();
+            statements.push(Statement { line: line_no, addr: pc as u16, item: Item::Bytes(values.clone()) });
+            pc += values.len() as u32;
+        } else {
+            let op = select(&keyword, rest, &symbols).map_err(error)?;
+            statements.push(Statement {
+                line: line_no,
+                addr: pc as u16,
+                item: Item::Instruction { op, operand: rest.to_string() },
+            });
+            pc += op.len as u32;
+        }
+        if pc > 0x10000 {
+            return Err(error("program runs past $FFFF".to_string()));
+        }
+    }
+
+    // second pass: every symbol is known, emit the bytes
+    let mut segments: Vec<Segment> = Vec::new();
+    for statement in statements {
+        let error = |message: String| AsmError { line: statement.line, message };
+        let resolve = |expr: &str| {
+            evaluate(expr, &symbols)
+                .map_err(error)?
+                .ok_or_else(|| error(format!("undefined symbol in {:?}", expr)))
+        };
+        let mut bytes = Vec::new();
+        match &statement.item {
+            Item::Bytes(values) => {
+                for value in values {
+                    bytes.push(byte(resolve(value)?).map_err(error)?);
+                }
+            }
+            Item::Instruction { op, operand } => {
+                bytes.push(op.code);
+                let expr = operand_expr(operand);
+                match op.mode {
+                    AddressingMode::Implied | AddressingMode::Accumulator => {}
+                    AddressingMode::Relative => {
+                        let target = resolve(expr)? as i32;
+                        let offset = target - (statement.addr as i32 + 2);
+                        if !(-128..=127).contains(&offset) {
+                            return Err(error(format!("branch target {} out of range", expr)));
+                        }
+                        bytes.push(offset as u8);
+                    }
+                    _ if op.len == 2 => bytes.push(byte(resolve(expr)?).map_err(error)?),
+                    _ => bytes.extend_from_slice(&resolve(expr)?.to_le_bytes()),
+                }
+            }
+        }
+        match segments.last_mut() {
+            Some(last) if last.addr as usize + last.data.len() == statement.addr as usize => {
+                last.data.extend_from_slice(&bytes);
+            }
+            _ => segments.push(Segment { addr: statement.addr, data: bytes }),
+        }
+    }
+    if segments.is_empty() {
+        return Err(AsmError { line: 0, message: "no code to assemble".to_string() });
+    }
+    Ok(Image { segments, entry: None })
+}
+
+/// Picks the opcode for `mnemonic` from the shape of its operand,
+/// preferring zero page when the address is already known to fit.
+fn select(mnemonic: &str, operand: &str, symbols: &BTreeMap<String, u16>) -> Result<&'static OpCode, String> {
+    if !opcodes::is_mnemonic(mnemonic) {
+        return Err(format!("unknown instruction {}", mnemonic));
+    }
+    let upper = operand.to_ascii_uppercase();
+    let expr = operand_expr(operand);
+    let modes: &[AddressingMode] = if operand.is_empty() {
+        &[AddressingMode::Implied, AddressingMode::Accumulator]
+    } else if upper == "A" {
+        &[AddressingMode::Accumulator]
+    } else if operand.starts_with('#') {
+        &[AddressingMode::Immediate]
+    } else if operand.starts_with('(') && upper.ends_with(",X)") {
+        &[AddressingMode::IndirectX]
+    } else if operand.starts_with('(') && upper.ends_with("),Y") {
+        &[AddressingMode::IndirectY]
+    } else if operand.starts_with('(') {
+        &[AddressingMode::Indirect]
+    } else if opcodes::find(mnemonic, AddressingMode::Relative).is_some() {
+        &[AddressingMode::Relative]
+    } else {
+        let short = matches!(evaluate(expr, symbols), Ok(Some(value)) if value < 0x100);
+        match (upper.ends_with(",X"), upper.ends_with(",Y"), short) {
+            (true, _, true) => &[AddressingMode::ZeroPageX, AddressingMode::AbsoluteX],
+            (true, _, false) => &[AddressingMode::AbsoluteX, AddressingMode::ZeroPageX],
+            (_, true, true) => &[AddressingMode::ZeroPageY, AddressingMode::AbsoluteY],
+            (_, true, false) => &[AddressingMode::AbsoluteY, AddressingMode::ZeroPageY],
+            (_, _, true) => &[AddressingMode::ZeroPage, AddressingMode::Absolute],
+            (_, _, false) => &[AddressingMode::Absolute, AddressingMode::ZeroPage],
+        }
+    };
+    modes
+        .iter()
+        .find_map(|&mode| opcodes::find(mnemonic, mode))
+        .ok_or_else(|| format!("{} does not take operand {:?}", mnemonic.to_ascii_uppercase(), operand))
+}
+
+/// The address or value part of an operand, without `#`, brackets or index.
+fn operand_expr(operand: &str) -> &str {
+    let mut expr = operand.trim_start_matches('#').trim_start_matches('(');
+    for suffix in [",X)", ",x)", "),Y", "),y", ",X", ",x", ",Y", ",y", ")"] {
+        if let Some(stripped) = expr.strip_suffix(suffix) {
+            expr = stripped;
+            break;
+        }
+    }
+    expr.trim()
+}
+
+fn byte(value: u16) -> Result<u8, String> {
+    u8::try_from(value).map_err(|_| format!("${:04x} does not fit in a byte", value))
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Evaluates `expr`, or `Ok(None)` when it names a symbol not defined yet.
+fn evaluate(expr: &str, symbols: &BTreeMap<String, u16>) -> Result<Option<u16>, String> {
+    if let Some(rest) = expr.strip_prefix('<') {
+        return Ok(evaluate(rest, symbols)?.map(|value| value & 0xff));
+    }
+    if let Some(rest) = expr.strip_prefix('>') {
+        return Ok(evaluate(rest, symbols)?.map(|value| value >> 8));
+    }
+    if let Some(pos) = expr.rfind(['+', '-']).filter(|&pos| pos > 0) {
+        let (lhs, rhs) = (evaluate(expr[..pos].trim(), symbols)?, evaluate(expr[pos + 1..].trim(), symbols)?);
+        return Ok(lhs.zip(rhs).map(|(lhs, rhs)| match &expr[pos..=pos] {
+            "+" => lhs.wrapping_add(rhs),
+            _ => lhs.wrapping_sub(rhs),
+        }));
+    }
+    let number = if let Some(hex) = expr.strip_prefix('$') {
+        u16::from_str_radix(hex, 16)
+    } else if let Some(bin) = expr.strip_prefix('%') {
+        u16::from_str_radix(bin, 2)
+    } else if expr.starts_with(|c: char| c.is_ascii_digit()) {
+        expr.parse()
+    } else if is_identifier(expr) {
+        return Ok(symbols.get(expr).copied());
+    } else {
+        return Err(format!("cannot parse {:?}", expr));
+    };
+    number.map(Some).map_err(|_| format!("bad number {:?}", expr))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_assemble() {
+        let source = "
+            define sysRandom $fe
+            define screen $0200
+            start:
+              jsr init      ; forward reference
+              lda sysRandom
+              sta screen,x
+              ldx #<table
+              ldy #%00000101
+              lda (ptr),y
+            loop:
+              dex
+              bne loop
+              asl
+              jmp (table)
+            init:
+              rts
+            table:
+              dcb $01, 2, >table
+        ";
+        let err = assemble(source, 0x0600).unwrap_err();
+        assert_eq!(err.line, 10, "ptr is not defined");
+
+        let source = format!("define ptr $10\n{}", source);
+        let image = assemble(&source, 0x0600).unwrap();
+        assert_eq!(image.entry(), 0x0600);
+        assert_eq!(
+            image.segments[0].data,
+            vec![
+                0x20, 0x15, 0x06, // jsr init
+                0xa5, 0xfe, // lda $fe
+                0x9d, 0x00, 0x02, // sta $0200,x
+                0xa2, 0x16, // ldx #<table
+                0xa0, 0x05, // ldy #5
+                0xb1, 0x10, // lda ($10),y
+                0xca, // dex
+                0xd0, 0xfd, // bne loop
+                0x0a, // asl
+                0x6c, 0x16, 0x06, // jmp (table)
+                0x60, // rts
+                0x01, 0x02, 0x06, // dcb
+            ]
+        );
+    }
+
+    #[test]
+    fn test_origin_and_errors() {
+        let image = assemble("*=$c000\nreset: nop\n*=$fffc\ndcb <reset, >reset\n", 0x0600).unwrap();
+        assert_eq!(image.segments.len(), 2);
+        assert_eq!(image.segments[1], Segment { addr: 0xfffc, data: vec![0x00, 0xc0] });
+        assert_eq!(image.entry(), 0xc000);
+
+        let err = assemble("nop\nfoo $10\n", 0x0600).unwrap_err();
+        assert_eq!(err.line, 2);
+        let err = assemble("lda #$100\n", 0x0600).unwrap_err();
+        assert_eq!(err, AsmError { line: 1, message: "$0100 does not fit in a byte".to_string() });
+        let err = assemble("stx $1234,x\n", 0x0600).unwrap_err();
+        assert_eq!(err.line, 1);
+    }
+}
@@ -0,0 +1,221 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+/// What the bus decodes to where no handler is mapped.
+pub trait Memory {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, val: u8);
+    /// The value at `addr` without side effects.
+    fn peek(&self, addr: u16) -> u8;
+
+    /// Whether `addr` decodes to a chip, such as ROM or a register, rather
+    /// than RAM.
+    fn is_mapped(&self, _addr: u16) -> bool {
+        false
+    }
+
+    /// Whether a write started a DMA that takes the bus away from the CPU,
+    /// such as the NES's OAM DMA. Reading it clears the request.
+    fn take_dma(&mut self) -> bool {
+        false
+    }
+
+    /// Lets clocked devices catch up with the CPU after an instruction.
+    fn tick(&mut self, _cycles: u64) {}
+
+    /// Whether NMI was asserted, clearing it. The CPU takes the interrupt
+    /// before its next instruction.
+    fn take_nmi(&mut self) -> bool {
+        false
+    }
+}
+
+/// A flat 64K of RAM.
+pub struct Ram(Box<[u8; 0x10000]>);
+
+impl Default for Ram {
+    fn default() -> Self {
+        Ram(Box::new([0; 0x10000]))
+    }
+}
+
+impl Memory for Ram {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.0[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.0[addr as usize] = val;
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.0[addr as usize]
+    }
+}
+
+type ReadHandler = Box<dyn FnMut(u16) -> u8>;
+type WriteHandler = Box<dyn FnMut(u16, u8)>;
+
+/// The 64K address space seen by the CPU: plain RAM, or another
+/// [`Memory`] such as a console's memory map, plus any number of
+/// memory-mapped devices. A handler owns its range for that direction
+/// only, so a device can be read-only and leave writes to land in RAM.
+/// Later registrations win where ranges overlap. Watchers, unlike
+/// handlers, leave the write to RAM and are only told about it.
+pub struct Bus {
+    memory: Box<dyn Memory>,
+    readers: Vec<(RangeInclusive<u16>, ReadHandler)>,
+    writers: Vec<(RangeInclusive<u16>, WriteHandler)>,
+    watchers: Vec<(RangeInclusive<u16>, WriteHandler)>,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Bus::new()
+    }
+}
+
+impl Bus {
+    pub fn new() -> Bus {
+        Bus::with_memory(Ram::default())
+    }
+
+    pub fn with_memory<M: Memory + 'static>(memory: M) -> Bus {
+        Bus {
+            memory: Box::new(memory),
+            readers: Vec::new(),
+            writers: Vec::new(),
+            watchers: Vec::new(),
+        }
+    }
+
+    pub fn on_read<F>(&mut self, range: RangeInclusive<u16>, handler: F)
+    where
+        F: FnMut(u16) -> u8 + 'static,
+    {
+        self.readers.push((range, Box::new(handler)));
+    }
+
+    pub fn on_write<F>(&mut self, range: RangeInclusive<u16>, handler: F)
+    where
+        F: FnMut(u16, u8) + 'static,
+    {
+        self.writers.push((range, Box::new(handler)));
+    }
+
+    pub fn watch<F>(&mut self, range: RangeInclusive<u16>, watcher: F)
+    where
+        F: FnMut(u16, u8) + 'static,
+    {
+        self.watchers.push((range, Box::new(watcher)));
+    }
+
+    /// Whether reads of `addr` go to a device rather than RAM.
+    pub fn is_mapped(&self, addr: u16) -> bool {
+        self.readers.iter().any(|(range, _)| range.contains(&addr)) || self.memory.is_mapped(addr)
+    }
+
+    pub fn read(&mut self, addr: u16) -> u8 {
+        match self.readers.iter_mut().rev().find(|(range, _)| range.contains(&addr)) {
+            Some((_, handler)) => handler(addr),
+            None => self.memory.read(addr),
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, val: u8) {
+        match self.writers.iter_mut().rev().find(|(range, _)| range.contains(&addr)) {
+            Some((_, handler)) => handler(addr, val),
+            None => {
+                self.memory.write(addr, val);
+                self.notify(addr, val);
+            }
+        }
+    }
+
+    fn notify(&mut self, addr: u16, val: u8) {
+        for (range, watcher) in &mut self.watchers {
+            if range.contains(&addr) {
+                watcher(addr, val);
+            }
+        }
+    }
+
+    /// Memory contents at `addr`, without side effects. Handlers are not
+    /// consulted, so this is what debuggers and the screen see.
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.memory.peek(addr)
+    }
+
+    pub fn take_dma(&mut self) -> bool {
+        self.memory.take_dma()
+    }
+
+    pub fn tick(&mut self, cycles: u64) {
+        self.memory.tick(cycles)
+    }
+
+    pub fn take_nmi(&mut self) -> bool {
+        self.memory.take_nmi()
+    }
+
+    /// Writes `data` straight to memory, bypassing any write handlers.
+    /// Watchers still see it.
+    pub fn load(&mut self, addr: u16, data: &[u8]) {
+        for (i, &val) in data.iter().enumerate() {
+            let addr = addr + i as u16;
+            self.memory.write(addr, val);
+            self.notify(addr, val);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_read_and_write_handlers() {
+        let mut bus = Bus::new();
+        bus.on_read(0xfe..=0xfe, |_| 0x42);
+        let out = Rc::new(RefCell::new(Vec::new()));
+        let sink = out.clone();
+        bus.on_write(0xd012..=0xd012, move |_, val| sink.borrow_mut().push(val));
+
+        bus.write(0xfe, 0x10);
+        assert_eq!(bus.read(0xfe), 0x42);
+        assert_eq!(bus.peek(0xfe), 0x10);
+        bus.write(0xd012, b'h');
+        bus.write(0xd012, b'i');
+        assert_eq!(*out.borrow(), b"hi");
+        assert_eq!(bus.peek(0xd012), 0);
+        assert!(bus.is_mapped(0xfe));
+        assert!(!bus.is_mapped(0xd012));
+    }
+
+    #[test]
+    fn test_later_handler_wins() {
+        let mut bus = Bus::new();
+        bus.on_read(0x4000..=0x40ff, |_| 1);
+        bus.on_read(0x4010..=0x4010, |_| 2);
+        assert_eq!(bus.read(0x4000), 1);
+        assert_eq!(bus.read(0x4010), 2);
+        bus.load(0x0200, &[7, 8]);
+        assert_eq!(bus.read(0x0201), 8);
+    }
+
+    #[test]
+    fn test_watchers_see_writes_to_ram() {
+        let mut bus = Bus::new();
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let sink = seen.clone();
+        bus.watch(0x0200..=0x05ff, move |addr, val| sink.borrow_mut().push((addr, val)));
+        bus.write(0x0200, 1);
+        bus.write(0x0600, 2);
+        bus.load(0x05ff, &[3, 4]);
+        assert_eq!(*seen.borrow(), vec![(0x0200, 1), (0x05ff, 3)]);
+        assert_eq!(bus.peek(0x0200), 1);
+    }
+}
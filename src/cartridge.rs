@@ -0,0 +1,443 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::nes::Device;
+use crate::scheduler::Clock;
+use crate::NTSC_FRAME_CYCLES;
+
+const MAGIC: &[u8; 4] = b"NES\x1a";
+const HEADER_LEN: usize = 16;
+const TRAINER_LEN: usize = 512;
+/// Where the trainer goes in PRG-RAM, i.e. $7000.
+const TRAINER_OFFSET: usize = 0x1000;
+const PRG_BANK: usize = 0x4000;
+const CHR_BANK: usize = 0x2000;
+const PRG_RAM_BANK: usize = 0x2000;
+
+/// How the PPU's two 1K nametables fill its four nametable slots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirroring {
+    /// $2000 = $2400 and $2800 = $2C00, for vertical scrolling.
+    Horizontal,
+    /// $2000 = $2800 and $2400 = $2C00, for horizontal scrolling.
+    Vertical,
+    SingleLower,
+    SingleUpper,
+    /// The cartridge supplies another 2K, so all four are distinct.
+    FourScreen,
+}
+
+/// The CPU/PPU timing a game was made for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    /// Runs on either; treated as NTSC.
+    MultiRegion,
+    /// The Famiclone timing: PAL frame rate with NTSC-like vblank.
+    Dendy,
+}
+
+impl Timing {
+    pub fn clock(self) -> Clock {
+        match self {
+            Timing::Ntsc | Timing::MultiRegion => Clock::NTSC,
+            Timing::Pal => Clock::PAL,
+            Timing::Dendy => Clock::DENDY,
+        }
+    }
+
+    /// CPU cycles per frame, rounded up.
+    pub fn frame_cycles(self) -> u64 {
+        match self {
+            Timing::Ntsc | Timing::MultiRegion => NTSC_FRAME_CYCLES,
+            Timing::Pal => 33248,
+            Timing::Dendy => 35464,
+        }
+    }
+}
+
+/// The hardware a game runs on, from NES 2.0 headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Console {
+    Nes,
+    /// The arcade Vs. System, with its PPU variant and protection
+    /// hardware as numbered by the NES 2.0 specification.
+    VsSystem { ppu: u8, hardware: u8 },
+    Playchoice10,
+    /// An extended console type, e.g. 3 for the VT01 famiclones.
+    Extended(u8),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CartridgeError {
+    BadMagic,
+    /// The file ends inside `section`.
+    Truncated { section: &'static str, expected: usize, found: usize },
+    NoPrgRom,
+    /// The header gives `section` a size too large to address.
+    RomTooLarge { section: &'static str },
+    UnsupportedMapper(u16),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartridgeError::BadMagic => write!(f, "not an iNES file (missing NES<EOF> signature)"),
+            CartridgeError::Truncated { section, expected, found } => {
+                write!(f, "file ends inside the {}: expected {} bytes, found {}", section, expected, found)
+            }
+            CartridgeError::NoPrgRom => write!(f, "header declares no PRG-ROM"),
+            CartridgeError::RomTooLarge { section } => write!(f, "header declares an impossibly large {}", section),
+            CartridgeError::UnsupportedMapper(mapper) => write!(f, "mapper {} is not supported", mapper),
+        }
+    }
+}
+
+/// A game cartridge as described by an iNES or NES 2.0 file. Only the
+/// board's memory is here; which banks appear where is up to the mapper.
+/// Fields only NES 2.0 headers carry have iNES-compatible defaults.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cartridge {
+    /// Whether the header was in NES 2.0 format.
+    pub nes2: bool,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub mirroring: Mirroring,
+    /// PRG-RAM is battery-backed, i.e. holds save games.
+    pub battery: bool,
+    /// 512 bytes some dumps expect at $7000, already copied into PRG-RAM.
+    pub trainer: Option<Vec<u8>>,
+    pub prg_rom: Vec<u8>,
+    /// CHR-ROM, or CHR-RAM when the file carries none.
+    pub chr: Vec<u8>,
+    pub chr_is_ram: bool,
+    /// Volatile PRG-RAM followed by battery-backed PRG-NVRAM.
+    pub prg_ram: Vec<u8>,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub timing: Timing,
+    pub console: Console,
+    /// The NES 2.0 default expansion device, 1 being standard
+    /// controllers and 0 unspecified.
+    pub expansion_device: u8,
+}
+
+impl Cartridge {
+    pub fn parse(bytes: &[u8]) -> Result<Cartridge, CartridgeError> {
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(CartridgeError::BadMagic);
+        }
+        let header = section(bytes, 0, HEADER_LEN, "header")?;
+        let flags6 = header[6];
+        let flags7 = header[7];
+        let nes2 = flags7 & 0x0c == 0x08;
+        // "DiskDude!" and other rippers' tags overwrite bytes 7-15
+        let dirty = !nes2 && header[12..].iter().any(|&byte| byte != 0);
+        let mut mapper = if dirty { flags6 >> 4 } else { flags7 & 0xf0 | flags6 >> 4 } as u16;
+        let mirroring = if flags6 & 0x08 != 0 {
+            Mirroring::FourScreen
+        } else if flags6 & 0x01 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+
+        let battery = flags6 & 0x02 != 0;
+
+        let mut submapper = 0;
+        let mut console = Console::Nes;
+        let mut expansion_device = 0;
+        let (prg_len, chr_len);
+        let (mut prg_ram_size, prg_nvram_size, mut chr_ram_size, mut chr_nvram_size);
+        let timing;
+        if nes2 {
+            mapper |= (header[8] as u16 & 0x0f) << 8;
+            submapper = header[8] >> 4;
+            prg_len = rom_size(header[4], header[9] & 0x0f, PRG_BANK)
+                .ok_or(CartridgeError::RomTooLarge { section: "PRG-ROM" })?;
+            chr_len = rom_size(header[5], header[9] >> 4, CHR_BANK)
+                .ok_or(CartridgeError::RomTooLarge { section: "CHR-ROM" })?;
+            prg_ram_size = ram_size(header[10] & 0x0f);
+            prg_nvram_size = ram_size(header[10] >> 4);
+            chr_ram_size = ram_size(header[11] & 0x0f);
+            chr_nvram_size = ram_size(header[11] >> 4);
+            timing = match header[12] & 0x03 {
+                0 => Timing::Ntsc,
+                1 => Timing::Pal,
+                2 => Timing::MultiRegion,
+                _ => Timing::Dendy,
+            };
+            console = match flags7 & 0x03 {
+                0 => Console::Nes,
+                1 => Console::VsSystem { ppu: header[13] & 0x0f, hardware: header[13] >> 4 },
+                2 => Console::Playchoice10,
+                _ => Console::Extended(header[13] & 0x0f),
+            };
+            expansion_device = header[15] & 0x3f;
+        } else {
+            prg_len = header[4] as usize * PRG_BANK;
+            chr_len = header[5] as usize * CHR_BANK;
+            // iNES can't say how much RAM there is, so assume the usual 8K
+            let ram = if dirty { 1 } else { header[8].max(1) as usize } * PRG_RAM_BANK;
+            (prg_ram_size, prg_nvram_size) = if battery { (0, ram) } else { (ram, 0) };
+            (chr_ram_size, chr_nvram_size) = (if chr_len == 0 { CHR_BANK } else { 0 }, 0);
+            timing = if !dirty && header[9] & 0x01 != 0 { Timing::Pal } else { Timing::Ntsc };
+        }
+        if prg_len == 0 {
+            return Err(CartridgeError::NoPrgRom);
+        }
+        if chr_len != 0 {
+            (chr_ram_size, chr_nvram_size) = (0, 0);
+        }
+        let has_trainer = flags6 & 0x04 != 0;
+        if has_trainer && prg_ram_size + prg_nvram_size < TRAINER_OFFSET + TRAINER_LEN {
+            // the trainer needs RAM at $7000 whatever the header says
+            prg_ram_size = PRG_RAM_BANK - prg_nvram_size;
+        }
+        let mut prg_ram = vec![0; prg_ram_size + prg_nvram_size];
+
+        let mut offset = HEADER_LEN;
+        let trainer = if has_trainer {
+            let trainer = section(bytes, offset, TRAINER_LEN, "trainer")?.to_vec();
+            prg_ram[TRAINER_OFFSET..TRAINER_OFFSET + TRAINER_LEN].copy_from_slice(&trainer);
+            offset += TRAINER_LEN;
+            Some(trainer)
+        } else {
+            None
+        };
+        let prg_rom = section(bytes, offset, prg_len, "PRG-ROM")?.to_vec();
+        offset += prg_len;
+        let chr = match chr_len {
+            0 => vec![0; chr_ram_size + chr_nvram_size],
+            _ => section(bytes, offset, chr_len, "CHR-ROM")?.to_vec(),
+        };
+
+        Ok(Cartridge {
+            nes2,
+            mapper,
+            submapper,
+            mirroring,
+            battery,
+            trainer,
+            prg_rom,
+            chr,
+            chr_is_ram: chr_len == 0,
+            prg_ram,
+            prg_ram_size,
+            prg_nvram_size,
+            chr_ram_size,
+            chr_nvram_size,
+            timing,
+            console,
+            expansion_device,
+        })
+    }
+
+    /// The PPU side of NROM: 8K of pattern tables at $0000-$1FFF.
+    pub fn chr_read(&self, addr: u16) -> u8 {
+        match self.chr.len() {
+            0 => 0,
+            len => self.chr[addr as usize % len],
+        }
+    }
+
+    pub fn chr_write(&mut self, addr: u16, val: u8) {
+        let len = self.chr.len();
+        if self.chr_is_ram && len > 0 {
+            self.chr[addr as usize % len] = val;
+        }
+    }
+
+    /// Fails for boards that aren't emulated.
+    pub fn check_supported(&self) -> Result<(), CartridgeError> {
+        match self.mapper {
+            0 => Ok(()),
+            mapper => Err(CartridgeError::UnsupportedMapper(mapper)),
+        }
+    }
+}
+
+/// A NES 2.0 ROM size: `msb:lsb` banks, or when the MSB nibble is $F,
+/// `lsb` as an exponent and multiplier, 2^E × (2M + 1) bytes. `None`
+/// if that doesn't fit in a `usize`.
+fn rom_size(lsb: u8, msb: u8, bank: usize) -> Option<usize> {
+    if msb == 0x0f {
+        let (exponent, multiplier) = (lsb >> 2, lsb as usize & 0x03);
+        let power = 1usize.checked_shl(exponent as u32)?;
+        power.checked_mul(multiplier * 2 + 1)
+    } else {
+        ((msb as usize) << 8 | lsb as usize).checked_mul(bank)
+    }
+}
+
+/// A NES 2.0 RAM size: 64 bytes shifted left by the nibble, or none.
+fn ram_size(shift: u8) -> usize {
+    match shift {
+        0 => 0,
+        shift => 64 << shift,
+    }
+}
+
+fn section<'a>(bytes: &'a [u8], offset: usize, len: usize, name: &'static str) -> Result<&'a [u8], CartridgeError> {
+    let end = offset.checked_add(len).ok_or(CartridgeError::RomTooLarge { section: name })?;
+    bytes.get(offset..end).ok_or(CartridgeError::Truncated {
+        section: name,
+        expected: len,
+        found: bytes.len().saturating_sub(offset),
+    })
+}
+
+/// The CPU side of NROM (mapper 0): PRG-RAM at $6000-$7FFF and 16K or
+/// 32K of PRG-ROM at $8000-$FFFF, a 16K ROM appearing twice.
+impl Device for Cartridge {
+    fn read(&mut self, addr: u16) -> Option<u8> {
+        self.peek(addr)
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        let len = self.prg_ram.len();
+        if let (0x6000..=0x7fff, 1..) = (addr, len) {
+            self.prg_ram[(addr as usize - 0x6000) % len] = val;
+        }
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7fff if self.prg_ram.is_empty() => None,
+            0x6000..=0x7fff => Some(self.prg_ram[(addr as usize - 0x6000) % self.prg_ram.len()]),
+            0x8000..=0xffff => Some(self.prg_rom[(addr as usize - 0x8000) % self.prg_rom.len()]),
+            _ => None,
+        }
+    }
+}
+
+/// An iNES file for tests: PRG-ROM counting up from 0, CHR-ROM of $CC
+/// and, if `flags6` asks for one, a trainer of $77.
+#[cfg(test)]
+pub(crate) fn ines(prg_banks: u8, chr_banks: u8, flags6: u8, flags7: u8) -> Vec<u8> {
+    let mut bytes = vec![b'N', b'E', b'S', 0x1a, prg_banks, chr_banks, flags6, flags7];
+    bytes.resize(HEADER_LEN, 0);
+    if flags6 & 0x04 != 0 {
+        bytes.extend([0x77; TRAINER_LEN]);
+    }
+    bytes.extend((0..prg_banks as usize * PRG_BANK).map(|i| i as u8));
+    bytes.extend(vec![0xcc; chr_banks as usize * CHR_BANK]);
+    bytes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_header() {
+        let cart = Cartridge::parse(&ines(2, 1, 0x13, 0x40)).unwrap();
+        assert_eq!(cart.mapper, 0x41);
+        assert_eq!(cart.mirroring, Mirroring::Vertical);
+        assert!(cart.battery);
+        assert_eq!(cart.prg_rom.len(), 0x8000);
+        assert_eq!((cart.chr.len(), cart.chr_is_ram), (0x2000, false));
+        assert_eq!(cart.prg_ram.len(), 0x2000);
+        assert_eq!(cart.check_supported(), Err(CartridgeError::UnsupportedMapper(0x41)));
+
+        let cart = Cartridge::parse(&ines(1, 0, 0x0c, 0)).unwrap();
+        assert_eq!(cart.mirroring, Mirroring::FourScreen);
+        assert!(cart.chr_is_ram);
+        assert_eq!(cart.trainer.as_deref(), Some(&[0x77; TRAINER_LEN][..]));
+        assert_eq!(cart.prg_ram[0x1000], 0x77);
+        assert!(cart.check_supported().is_ok());
+
+        let mut dirty = ines(1, 1, 0x10, 0x20);
+        dirty[7..16].copy_from_slice(b"DiskDude!");
+        assert_eq!(Cartridge::parse(&dirty).unwrap().mapper, 1);
+    }
+
+    #[test]
+    fn test_nes2_header() {
+        let mut file = ines(2, 0, 0x21, 0x19);
+        file[8..16].copy_from_slice(&[0x35, 0x00, 0x70, 0x07, 0x01, 0x21, 0x00, 0x01]);
+        let cart = Cartridge::parse(&file).unwrap();
+        assert!(cart.nes2);
+        assert_eq!((cart.mapper, cart.submapper), (0x512, 3));
+        assert_eq!(cart.mirroring, Mirroring::Vertical);
+        assert_eq!(cart.prg_rom.len(), 0x8000);
+        assert_eq!((cart.prg_ram_size, cart.prg_nvram_size), (0, 0x2000));
+        assert_eq!((cart.chr_ram_size, cart.chr_nvram_size), (0x2000, 0));
+        assert_eq!((cart.prg_ram.len(), cart.chr.len(), cart.chr_is_ram), (0x2000, 0x2000, true));
+        assert_eq!(cart.timing, Timing::Pal);
+        assert_eq!(cart.timing.clock(), Clock::PAL);
+        assert_eq!(cart.console, Console::VsSystem { ppu: 1, hardware: 2 });
+        assert_eq!(cart.expansion_device, 1);
+
+        // no PRG-RAM at all: $6000 is open bus
+        file[10] = 0;
+        file[12] = 0x03;
+        let cart = Cartridge::parse(&file).unwrap();
+        assert!(cart.prg_ram.is_empty());
+        assert_eq!(cart.peek(0x6000), None);
+        assert_eq!(cart.timing.frame_cycles(), 35464);
+
+        // but a trainer gets RAM to load into anyway
+        file[6] |= 0x04;
+        file.splice(HEADER_LEN..HEADER_LEN, [0x77; TRAINER_LEN]);
+        let cart = Cartridge::parse(&file).unwrap();
+        assert_eq!((cart.prg_ram_size, cart.prg_ram.len()), (0x2000, 0x2000));
+        assert_eq!(cart.peek(0x7000), Some(0x77));
+
+        // the old header leaves NES 2.0 fields at their defaults
+        let cart = Cartridge::parse(&ines(1, 0, 0x02, 0)).unwrap();
+        assert!(!cart.nes2);
+        assert_eq!((cart.prg_ram_size, cart.prg_nvram_size, cart.chr_ram_size), (0, 0x2000, 0x2000));
+        assert_eq!((cart.timing, cart.console), (Timing::Ntsc, Console::Nes));
+    }
+
+    #[test]
+    fn test_exponent_rom_sizes() {
+        assert_eq!(rom_size(0x02, 0x00, PRG_BANK), Some(0x8000));
+        assert_eq!(rom_size(0x00, 0x01, CHR_BANK), Some(0x100 * CHR_BANK));
+        // 2^6 × 3
+        assert_eq!(rom_size(0x19, 0x0f, PRG_BANK), Some(192));
+        // 2^63 × 7
+        assert_eq!(rom_size(0xff, 0x0f, PRG_BANK), None);
+        assert_eq!(ram_size(0), 0);
+        assert_eq!(ram_size(7), 0x2000);
+    }
+
+    #[test]
+    fn test_malformed_files() {
+        assert_eq!(Cartridge::parse(b"NES"), Err(CartridgeError::BadMagic));
+        assert_eq!(Cartridge::parse(&[0; 32]), Err(CartridgeError::BadMagic));
+        assert_eq!(Cartridge::parse(&ines(0, 1, 0, 0)), Err(CartridgeError::NoPrgRom));
+        let truncated = Cartridge::parse(&ines(2, 1, 0, 0)[..HEADER_LEN + 0x5000]);
+        assert_eq!(
+            truncated,
+            Err(CartridgeError::Truncated { section: "PRG-ROM", expected: 0x8000, found: 0x5000 })
+        );
+        let err = Cartridge::parse(&ines(1, 1, 0, 0)[..HEADER_LEN + PRG_BANK + 10]).unwrap_err();
+        assert_eq!(err.to_string(), "file ends inside the CHR-ROM: expected 8192 bytes, found 10");
+
+        // NES 2.0 exponent sizes of 2^63 × 7, too large for any usize
+        let mut huge = b"NES\x1a\xff\x01\x00\x08\x00\x0f".to_vec();
+        huge.resize(HEADER_LEN + 16, 0);
+        assert_eq!(Cartridge::parse(&huge), Err(CartridgeError::RomTooLarge { section: "PRG-ROM" }));
+        huge[4..6].copy_from_slice(&[0x01, 0xff]);
+        huge[9] = 0xf0;
+        assert_eq!(Cartridge::parse(&huge), Err(CartridgeError::RomTooLarge { section: "CHR-ROM" }));
+    }
+
+    #[test]
+    fn test_nrom_mapping() {
+        let mut cart = Cartridge::parse(&ines(1, 1, 0, 0)).unwrap();
+        assert_eq!(cart.read(0x8001), Some(1));
+        assert_eq!(cart.read(0xc001), Some(1));
+        cart.write(0x8001, 0xff);
+        assert_eq!(cart.read(0x8001), Some(1));
+        cart.write(0x6010, 0x42);
+        assert_eq!(cart.read(0x6010), Some(0x42));
+        assert_eq!(cart.read(0x5000), None);
+    }
+}
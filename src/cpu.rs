@@ -0,0 +1,1578 @@
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+
+use bitflags::bitflags;
+
+use crate::bus::Bus;
+use crate::loader::{Image, LoadError};
+use crate::opcodes;
+use crate::sanitizer::{Sanitizer, SanitizerConfig};
+
+bitflags! {
+    /// The P register.
+    pub struct ProcessorStatus: u8 {
+        const CARRY             = 0b0000_0001;
+        const ZERO              = 0b0000_0010;
+        const INTERRUPT_DISABLE = 0b0000_0100;
+        const DECIMAL_MODE      = 0b0000_1000;
+        const BREAK             = 0b0001_0000;
+        const BREAK2            = 0b0010_0000;
+        const OVERFLOW          = 0b0100_0000;
+        const NEGATIVE          = 0b1000_0000;
+    }
+}
+
+const STACK: u16 = 0x0100;
+const NMI_VECTOR: u16 = 0xfffa;
+const STACK_RESET: u8 = 0xfd;
+/// CPU cycles per NTSC frame: 1.789773 MHz / 60.0988 Hz.
+pub const NTSC_FRAME_CYCLES: u64 = 29781;
+
+/// Why a run returned control to the caller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The cycle or instruction budget ran out.
+    BudgetExhausted,
+    /// `run_until`'s predicate became true.
+    Condition,
+    Breakpoint(u16),
+    /// A BRK instruction was executed.
+    Break,
+    /// An unimplemented or jamming opcode; `pc` still points at it.
+    Jam(u8),
+    /// The frontend asked to stop, e.g. the window was closed.
+    Quit,
+    /// The sanitizer reported an error.
+    Sanitizer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressingMode {
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    IndirectX,
+    IndirectY,
+    Indirect,
+    Relative,
+    Accumulator,
+    Implied,
+}
+
+/// A 6502 core with its bus. The registers are public so that embedders
+/// and debuggers can inspect and patch state between instructions.
+#[allow(clippy::upper_case_acronyms)]
+pub struct CPU {
+    /// Accumulator.
+    pub ra: u8,
+    pub rx: u8,
+    pub ry: u8,
+    /// Stack pointer, an offset into page one.
+    pub rs: u8,
+    pub pc: u16,
+    pub rp: ProcessorStatus,
+    pub(crate) bus: Bus,
+    cycles: u64,
+    frame_cycles: u64,
+    page_crossed: bool,
+    extra_cycles: u8,
+    /// Bus accesses the current instruction has made, so the bus can be
+    /// caught up to each one; `None` between instructions.
+    accesses: Option<u64>,
+    breakpoints: BTreeSet<u16>,
+    sanitizer: Option<Sanitizer>,
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        CPU::new()
+    }
+}
+
+impl CPU {
+    pub fn new() -> CPU {
+        CPU::with_bus(Bus::new())
+    }
+
+    /// A CPU on `bus`, e.g. one built on a console's memory map.
+    pub fn with_bus(bus: Bus) -> CPU {
+        CPU {
+            ra: 0,
+            rx: 0,
+            ry: 0,
+            rs: STACK_RESET,
+            pc: 0,
+            rp: ProcessorStatus::BREAK2 | ProcessorStatus::INTERRUPT_DISABLE,
+            bus,
+            cycles: 0,
+            frame_cycles: NTSC_FRAME_CYCLES,
+            page_crossed: false,
+            extra_cycles: 0,
+            accesses: None,
+            breakpoints: BTreeSet::new(),
+            sanitizer: None,
+        }
+    }
+
+    pub fn bus(&self) -> &Bus {
+        &self.bus
+    }
+
+    /// For mapping devices onto the address space.
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+
+    pub fn enable_sanitizer(&mut self, config: SanitizerConfig) {
+        self.sanitizer = Some(Sanitizer::new(config));
+    }
+
+    pub fn sanitizer(&self) -> Option<&Sanitizer> {
+        self.sanitizer.as_ref()
+    }
+
+    fn get_operand_address(&mut self, mode: AddressingMode) -> u16 {
+        match mode {
+            AddressingMode::Immediate => self.pc,
+            AddressingMode::ZeroPage => self.mem_read(self.pc) as u16,
+            AddressingMode::Absolute => self.mem_read_u16(self.pc),
+            AddressingMode::ZeroPageX => {
+                self.mem_read(self.pc).wrapping_add(self.rx) as u16
+            }
+            AddressingMode::ZeroPageY => {
+                self.mem_read(self.pc).wrapping_add(self.ry) as u16
+            }
+            AddressingMode::AbsoluteX => {
+                let base = self.mem_read_u16(self.pc);
+                self.indexed(base, self.rx)
+            }
+            AddressingMode::AbsoluteY => {
+                let base = self.mem_read_u16(self.pc);
+                self.indexed(base, self.ry)
+            }
+            AddressingMode::IndirectX => {
+                let addr = self.mem_read(self.pc).wrapping_add(self.rx) as u16;
+                let lo = self.mem_read(addr);
+                let hi = self.mem_read(addr.wrapping_add(1));
+                (hi as u16) << 8 | (lo as u16)
+            }
+            AddressingMode::IndirectY => {
+                let addr = self.mem_read(self.pc) as u16;
+                let lo = self.mem_read(addr);
+                let hi = self.mem_read(addr.wrapping_add(1));
+                let deref_base = (hi as u16) << 8 | (lo as u16);
+                self.indexed(deref_base, self.ry)
+            }
+            AddressingMode::Indirect
+            | AddressingMode::Relative
+            | AddressingMode::Accumulator
+            | AddressingMode::Implied => {
+                panic!("mode {:?} is not supported", mode);
+            }
+        }
+    }
+
+    fn indexed(&mut self, base: u16, index: u8) -> u16 {
+        let addr = base.wrapping_add(index as u16);
+        self.page_crossed = base & 0xff00 != addr & 0xff00;
+        addr
+    }
+
+    fn update_negative_flag(&mut self, reg: u8) {
+        self.rp.set(ProcessorStatus::NEGATIVE, reg & 0b1000_0000 != 0);
+    }
+
+    fn update_zero_and_negative_flags(&mut self, reg: u8) {
+        self.rp.set(ProcessorStatus::ZERO, reg == 0);
+        self.update_negative_flag(reg);
+    }
+
+    fn set_reg_a(&mut self, val: u8) {
+        self.ra = val;
+        self.update_zero_and_negative_flags(self.ra);
+    }
+
+    fn add_to_reg_a(&mut self, val: u8) {
+        let s = self.ra as u16 +
+            val as u16 +
+            if self.rp.contains(ProcessorStatus::CARRY) {1} else {0} as u16;
+        self.rp.set(ProcessorStatus::CARRY, 0xff < s);
+        let result = s as u8;
+        self.rp.set(ProcessorStatus::OVERFLOW, (val ^ result) & (self.ra ^ result) & 0x80 != 0);
+        self.set_reg_a(result);
+    }
+
+    fn adc(&mut self, mode: AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let val = self.mem_read(addr);
+        self.add_to_reg_a(val);
+    }
+
+    fn and(&mut self, mode: AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let val = self.mem_read(addr);
+        self.set_reg_a(self.ra & val);
+    }
+
+    fn asl_accumulator(&mut self) {
+        let val = self.ra;
+        self.rp.set(ProcessorStatus::CARRY, val & 0x80 != 0);
+        self.set_reg_a(val << 1);
+    }
+
+    fn asl(&mut self, mode: AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let val = self.mem_read(addr);
+        self.rp.set(ProcessorStatus::CARRY, val & 0x80 != 0);
+        let result = val << 1;
+        self.mem_write(addr, result);
+        self.update_zero_and_negative_flags(result);
+    }
+
+    fn branch(&mut self, condition: bool) {
+        // if condition {
+        //     let offset = self.mem_read(self.pc) as u16;
+        //     self.pc = self.pc.wrapping_add(1).wrapping_add(offset);
+        // }
+        if condition {
+            let jump: i8 = self.mem_read(self.pc) as i8;
+            let jump_addr = self
+                .pc
+                .wrapping_add(1)
+                .wrapping_add(jump as u16);
+
+            self.extra_cycles += 1;
+            if self.pc.wrapping_add(1) & 0xff00 != jump_addr & 0xff00 {
+                self.extra_cycles += 1;
+            }
+            self.pc = jump_addr;
+        } else {
+            self.pc += 1;
+        }
+    }
+
+    fn bbc(&mut self) {
+        self.branch(!self.rp.contains(ProcessorStatus::CARRY));
+    }
+
+    fn bcs(&mut self) {
+        self.branch(self.rp.contains(ProcessorStatus::CARRY));
+    }
+
+    fn beq(&mut self) {
+        self.branch(self.rp.contains(ProcessorStatus::ZERO));
+    }
+
+    fn bit(&mut self, mode: AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let val = self.mem_read(addr);
+        self.rp.set(ProcessorStatus::ZERO, self.ra & val == 0);
+        self.rp.set(ProcessorStatus::OVERFLOW, val & 0b0100_0000 != 0);
+        self.rp.set(ProcessorStatus::NEGATIVE, val & 0b1000_0000 != 0);
+    }
+
+    fn bmi(&mut self) {
+        self.branch(self.rp.contains(ProcessorStatus::NEGATIVE));
+    }
+
+    fn bne(&mut self) {
+        self.branch(!self.rp.contains(ProcessorStatus::ZERO));
+    }
+
+    fn bpl(&mut self) {
+        self.branch(!self.rp.contains(ProcessorStatus::NEGATIVE));
+    }
+
+    fn bvc(&mut self) {
+        self.branch(!self.rp.contains(ProcessorStatus::OVERFLOW));
+    }
+
+    fn bvs(&mut self) {
+        self.branch(self.rp.contains(ProcessorStatus::OVERFLOW));
+    }
+
+    fn clc(&mut self) {
+        self.rp.remove(ProcessorStatus::CARRY);
+    }
+
+    fn cld(&mut self) {
+        self.rp.remove(ProcessorStatus::DECIMAL_MODE);
+    }
+
+    fn cli(&mut self) {
+        self.rp.remove(ProcessorStatus::INTERRUPT_DISABLE);
+    }
+
+    fn clv(&mut self) {
+        self.rp.remove(ProcessorStatus::OVERFLOW);
+    }
+
+    fn compare(&mut self, mode: AddressingMode, other: u8) {
+        let addr = self.get_operand_address(mode);
+        let val = self.mem_read(addr);
+        self.rp.set(ProcessorStatus::CARRY, val <= other);
+        self.update_zero_and_negative_flags(other.wrapping_sub(val));
+    }
+
+    fn cmp(&mut self, mode: AddressingMode) {
+        self.compare(mode, self.ra);
+    }
+
+    fn cpx(&mut self, mode: AddressingMode) {
+        self.compare(mode, self.rx);
+    }
+
+    fn cpy(&mut self, mode: AddressingMode) {
+        self.compare(mode, self.ry);
+    }
+
+    fn dec(&mut self, mode:AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let val = self.mem_read(addr);
+        let result = val.wrapping_sub(1);
+        self.mem_write(addr, result);
+        self.update_zero_and_negative_flags(result);
+    }
+
+    fn dex(&mut self) {
+        self.rx = self.rx.wrapping_sub(1);
+        self.update_zero_and_negative_flags(self.rx);
+    }
+
+    fn dey(&mut self) {
+        self.ry = self.ry.wrapping_sub(1);
+        self.update_zero_and_negative_flags(self.ry);
+    }
+
+    fn eor(&mut self, mode: AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let val = self.mem_read(addr);
+        self.set_reg_a(self.ra ^ val);
+    }
+
+    fn inc(&mut self, mode: AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let val = self.mem_read(addr).wrapping_add(1);
+        self.mem_write(addr, val);
+        self.update_zero_and_negative_flags(val);
+    }
+
+    fn inx(&mut self) {
+        self.rx = self.rx.wrapping_add(1);
+        self.update_zero_and_negative_flags(self.rx);
+    }
+
+    fn iny(&mut self) {
+        self.ry = self.ry.wrapping_add(1);
+        self.update_zero_and_negative_flags(self.ry);
+    }
+
+    fn jmp_absolute(&mut self) {
+        let addr = self.mem_read_u16(self.pc);
+        self.pc = addr;
+    }
+
+    fn jmp_indirect(&mut self) {
+        let addr = self.mem_read_u16(self.pc);
+        let indirect_addr = if addr & 0x00ff == 0x00ff {
+            let lo = self.mem_read(addr);
+            let hi = self.mem_read(addr & 0xff00);
+            ((hi as u16) << 8) | (lo as u16)
+        } else {
+            self.mem_read_u16(addr)
+        };
+        self.pc = indirect_addr;
+    }
+
+    fn jsr(&mut self) {
+        if let Some(sanitizer) = self.sanitizer.as_mut() {
+            sanitizer.on_call(self.pc - 1, self.pc + 1);
+        }
+        self.stack_push_u16(self.pc + 1);
+        let addr = self.mem_read_u16(self.pc);
+        self.pc = addr;
+    }
+
+    fn lda(&mut self, mode: AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let val = self.mem_read(addr);
+        self.set_reg_a(val);
+    }
+
+    fn ldx(&mut self, mode: AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let val = self.mem_read(addr);
+        self.rx = val;
+        self.update_zero_and_negative_flags(self.rx);
+    }
+
+    fn ldy(&mut self, mode: AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let val = self.mem_read(addr);
+        self.ry = val;
+        self.update_zero_and_negative_flags(self.ry);
+    }
+
+    fn lsr_accumulator(&mut self) {
+        let val = self.ra;
+        self.rp.set(ProcessorStatus::CARRY, val & 0x1 != 0);
+        self.set_reg_a(val >> 1);
+    }
+
+    fn lsr(&mut self, mode: AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let val = self.mem_read(addr);
+        self.rp.set(ProcessorStatus::CARRY, val & 0x1 != 0);
+        let result = val >> 1;
+        self.mem_write(addr, result);
+        self.update_zero_and_negative_flags(result);
+    }
+
+    fn ora(&mut self, mode: AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let val = self.mem_read(addr);
+        self.set_reg_a(self.ra | val);
+    }
+
+    fn pha(&mut self) {
+        self.stack_push(self.ra);
+    }
+
+    fn php(&mut self) {
+        let mut rp = self.rp;
+        rp.insert(ProcessorStatus::BREAK);
+        rp.insert(ProcessorStatus::BREAK2);
+        self.stack_push(rp.bits());
+    }
+
+    fn pla(&mut self) {
+        let val = self.stack_pop();
+        self.set_reg_a(val);
+    }
+
+    fn plp(&mut self) {
+        self.rp.bits = self.stack_pop();
+        self.rp.remove(ProcessorStatus::BREAK);
+        self.rp.insert(ProcessorStatus::BREAK2);
+    }
+
+    fn rol_accumulator(&mut self) {
+        let mut val = self.ra;
+        let c = self.rp.contains(ProcessorStatus::CARRY);
+        self.rp.set(ProcessorStatus::CARRY, val & 0x80 != 0);
+        val <<= 1;
+        if c {
+            val |= 1;
+        }
+        self.set_reg_a(val);
+    }
+
+    fn rol(&mut self, mode: AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let mut val = self.mem_read(addr);
+        let c = self.rp.contains(ProcessorStatus::CARRY);
+        self.rp.set(ProcessorStatus::CARRY, val & 0x80 != 0);
+        val <<= 1;
+        if c {
+            val |= 1;
+        }
+        self.mem_write(addr, val);
+        self.update_negative_flag(val);
+    }
+
+    fn ror_accumulator(&mut self) {
+        let mut val = self.ra;
+        let c = self.rp.contains(ProcessorStatus::CARRY);
+        self.rp.set(ProcessorStatus::CARRY, val & 0x1 != 0);
+        val >>= 1;
+        if c {
+            val |= 0b1000_0000;
+        }
+        self.set_reg_a(val);
+    }
+
+    fn ror(&mut self, mode: AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let mut val = self.mem_read(addr);
+        let c = self.rp.contains(ProcessorStatus::CARRY);
+        self.rp.set(ProcessorStatus::CARRY, val & 0x1 != 0);
+        val >>= 1;
+        if c {
+            val |= 0b1000_0000;
+        }
+        self.mem_write(addr, val);
+        self.update_negative_flag(val);
+
+    }
+
+    fn rti(&mut self) {
+        self.rp.bits = self.stack_pop();
+        self.rp.remove(ProcessorStatus::BREAK);
+        self.rp.insert(ProcessorStatus::BREAK2);
+        self.pc = self.stack_pop_u16();
+    }
+
+    /// Enters a hardware interrupt handler, taking 7 cycles.
+    fn interrupt(&mut self, vector: u16) {
+        self.stack_push_u16(self.pc);
+        let mut rp = self.rp;
+        rp.remove(ProcessorStatus::BREAK);
+        rp.insert(ProcessorStatus::BREAK2);
+        self.stack_push(rp.bits());
+        self.rp.insert(ProcessorStatus::INTERRUPT_DISABLE);
+        self.pc = self.mem_read_u16(vector);
+        self.cycles += 7;
+    }
+
+    fn rts(&mut self) {
+        let ret = self.stack_pop_u16();
+        if let Some(sanitizer) = self.sanitizer.as_mut() {
+            sanitizer.on_return(ret);
+        }
+        self.pc = ret.wrapping_add(1);
+    }
+
+    fn sbc(&mut self, mode: AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let val = self.mem_read(addr);
+        self.add_to_reg_a((val as i8).wrapping_neg().wrapping_sub(1) as u8);
+    }
+
+    fn sec(&mut self) {
+        self.rp.insert(ProcessorStatus::CARRY);
+    }
+
+    fn sed(&mut self) {
+        self.rp.insert(ProcessorStatus::DECIMAL_MODE);
+    }
+
+    fn sei(&mut self) {
+        self.rp.insert(ProcessorStatus::INTERRUPT_DISABLE);
+    }
+
+    fn sta(&mut self, mode: AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        self.mem_write(addr, self.ra);
+    }
+
+    fn stx(&mut self, mode: AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        self.mem_write(addr, self.rx);
+    }
+
+    fn sty(&mut self, mode: AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        self.mem_write(addr, self.ry);
+    }
+
+    fn tax(&mut self) {
+        self.rx = self.ra;
+        self.update_zero_and_negative_flags(self.rx);
+    }
+
+    fn tay(&mut self) {
+        self.ry = self.ra;
+        self.update_zero_and_negative_flags(self.ry);
+    }
+
+    fn tsx(&mut self) {
+        self.rx = self.rs;
+        self.update_zero_and_negative_flags(self.rx);
+    }
+
+    fn txa(&mut self) {
+        self.ra = self.rx;
+        self.update_zero_and_negative_flags(self.ra);
+    }
+
+    fn txs(&mut self) {
+        self.rs = self.rx;
+    }
+
+    fn tya(&mut self) {
+        self.ra = self.ry;
+        self.update_zero_and_negative_flags(self.ra);
+    }
+
+    pub fn mem_peek(&self, addr: u16) -> u8 {
+        self.bus.peek(addr)
+    }
+
+    pub fn mem_read(&mut self, addr: u16) -> u8 {
+        self.catch_up();
+        if let Some(sanitizer) = self.sanitizer.as_mut() {
+            // devices are initialised by definition
+            if !self.bus.is_mapped(addr) {
+                sanitizer.on_read(addr);
+            }
+        }
+        self.bus.read(addr)
+    }
+
+    pub fn mem_write(&mut self, addr: u16, val: u8) {
+        self.catch_up();
+        if let Some(sanitizer) = self.sanitizer.as_mut() {
+            sanitizer.on_write(addr);
+        }
+        self.bus.write(addr, val);
+    }
+
+    /// Ticks the bus up to the cycle of the access about to be made,
+    /// taking each access of an instruction to be a cycle after the last.
+    fn catch_up(&mut self) {
+        if let Some(accesses) = self.accesses.as_mut() {
+            if *accesses > 0 {
+                self.bus.tick(1);
+            }
+            *accesses += 1;
+        }
+    }
+
+    pub fn mem_read_u16(&mut self, pos: u16) -> u16 {
+        let lo = self.mem_read(pos);
+        let hi = self.mem_read(pos + 1);
+        u16::from_le_bytes([lo, hi])
+    }
+
+    pub fn mem_write_u16(&mut self, pos: u16, val: u16) {
+        let hi = (val >> 8) as u8;
+        let lo = (val & 0xff) as u8;
+        self.mem_write(pos, lo);
+        self.mem_write(pos + 1, hi);
+    }
+
+    fn stack_pop(&mut self) -> u8 {
+        if let Some(sanitizer) = self.sanitizer.as_mut() {
+            sanitizer.on_pop(self.rs, STACK + self.rs.wrapping_add(1) as u16);
+        }
+        self.rs = self.rs.wrapping_add(1);
+        self.mem_read(STACK + self.rs as u16)
+    }
+
+    fn stack_pop_u16(&mut self) -> u16 {
+        let lo = self.stack_pop() as u16;
+        let hi = self.stack_pop() as u16;
+        (hi << 8) | lo
+    }
+
+    fn stack_push(&mut self, val: u8) {
+        if let Some(sanitizer) = self.sanitizer.as_mut() {
+            sanitizer.on_push(self.rs, STACK + self.rs as u16);
+        }
+        self.mem_write(STACK + self.rs as u16, val);
+        self.rs = self.rs.wrapping_sub(1);
+    }
+
+    fn stack_push_u16(&mut self, val: u16) {
+        let hi = (val >> 8) as u8;
+        let lo = (val & 0xff) as u8;
+        self.stack_push(hi);
+        self.stack_push(lo);
+    }
+
+    pub fn reset(&mut self) {
+        self.ra = 0;
+        self.rx = 0;
+        self.ry = 0;
+        self.rs = STACK_RESET;
+        self.rp = ProcessorStatus::BREAK2 | ProcessorStatus::INTERRUPT_DISABLE;
+        self.pc = self.mem_read_u16(0xFFFC);
+        self.cycles = 7;
+    }
+
+    pub fn load(&mut self, program: Vec<u8>) -> Result<(), LoadError> {
+        self.load_image(&Image::raw(&program, 0x0600), None)
+    }
+
+    /// Copies every segment of `image` into memory and points the reset
+    /// vector at `entry`. Without an explicit entry an image that fills in
+    /// the reset vector itself keeps it; otherwise its start address is used.
+    pub fn load_image(&mut self, image: &Image, entry: Option<u16>) -> Result<(), LoadError> {
+        image.validate()?;
+        let mut has_vector = false;
+        for segment in &image.segments {
+            let start = segment.addr as usize;
+            let end = start + segment.data.len();
+            self.bus.load(segment.addr, &segment.data);
+            if let Some(sanitizer) = self.sanitizer.as_mut() {
+                sanitizer.on_load(segment.addr, segment.data.len());
+            }
+            has_vector |= start <= 0xfffc && 0xfffd < end;
+        }
+        match entry {
+            Some(entry) => self.mem_write_u16(0xFFFC, entry),
+            None if has_vector => {}
+            None => self.mem_write_u16(0xFFFC, image.entry()),
+        }
+        Ok(())
+    }
+
+    pub fn load_and_run(&mut self, program: Vec<u8>) {
+        self.load(program).expect("program does not fit in memory");
+        self.reset();
+        self.run();
+    }
+
+    pub fn run(&mut self) -> StopReason {
+        self.run_with_callback(|_| {})
+    }
+
+    pub fn run_with_callback<F>(&mut self, mut callback: F) -> StopReason where F: FnMut(&mut CPU) {
+        let mut first = true;
+        loop {
+            if !first && self.breakpoints.contains(&self.pc) {
+                return StopReason::Breakpoint(self.pc);
+            }
+            first = false;
+
+            if let Some(reason) = self.step() {
+                return reason;
+            }
+
+            callback(self);
+        }
+    }
+
+    /// Executes one instruction.
+    pub fn step(&mut self) -> Option<StopReason> {
+        let start = self.cycles;
+        if self.sanitizer.is_some() {
+            let len = opcodes::lookup(self.mem_peek(self.pc)).map_or(1, |op| op.len);
+            if let Some(sanitizer) = self.sanitizer.as_mut() {
+                sanitizer.on_fetch(self.pc, len);
+            }
+        }
+        self.accesses = Some(0);
+        let opscode = self.mem_read(self.pc);
+        let Some(op) = opcodes::lookup(opscode) else {
+            self.accesses = None;
+            return Some(StopReason::Jam(opscode));
+        };
+
+        self.pc += 1;
+        self.page_crossed = false;
+        self.extra_cycles = 0;
+
+        match opscode {
+            0x69 => {
+                self.adc(AddressingMode::Immediate);
+                self.pc += 1;
+            }
+            0x65 => {
+                self.adc(AddressingMode::ZeroPage);
+                self.pc += 1;
+            }
+            0x75 => {
+                self.adc(AddressingMode::ZeroPageX);
+                self.pc += 1;
+            }
+            0x6d => {
+                self.adc(AddressingMode::Absolute);
+                self.pc += 2;
+            }
+            0x7d => {
+                self.adc(AddressingMode::AbsoluteX);
+                self.pc += 2;
+            }
+            0x79 => {
+                self.adc(AddressingMode::AbsoluteX);
+                self.pc += 2;
+            }
+            0x61 => {
+                self.adc(AddressingMode::IndirectX);
+                self.pc += 1;
+            }
+            0x71 => {
+                self.adc(AddressingMode::IndirectY);
+                self.pc += 1;
+            }
+            0x29 => {
+                self.and(AddressingMode::Immediate);
+                self.pc += 1;
+            }
+            0x25 => {
+                self.and(AddressingMode::ZeroPage);
+                self.pc += 1;
+            }
+            0x35 => {
+                self.and(AddressingMode::ZeroPageX);
+                self.pc += 1
+            }
+            0x2d => {
+                self.and(AddressingMode::Absolute);
+                self.pc += 2;
+            }
+            0x3d => {
+                self.and(AddressingMode::AbsoluteX);
+                self.pc += 2;
+            }
+            0x39 => {
+                self.and(AddressingMode::AbsoluteY);
+                self.pc += 2;
+            }
+            0x21 => {
+                self.and(AddressingMode::IndirectX);
+                self.pc += 1;
+            }
+            0x31 => {
+                self.and(AddressingMode::IndirectY);
+                self.pc += 1;
+            }
+            0x0a => {
+                self.asl_accumulator();
+            }
+            0x06 => {
+                self.asl(AddressingMode::ZeroPage);
+                self.pc += 1;
+            }
+            0x16 => {
+                self.asl(AddressingMode::ZeroPageX);
+                self.pc += 1;
+            }
+            0x0e => {
+                self.asl(AddressingMode::Absolute);
+                self.pc += 2;
+            }
+            0x1e => {
+                self.asl(AddressingMode::AbsoluteX);
+                self.pc += 2;
+            }
+            0x90 => {
+                self.bbc();
+            }
+            0xb0 => {
+                self.bcs();
+            }
+            0xf0 => {
+                self.beq();
+            }
+            0x24 => {
+                self.bit(AddressingMode::ZeroPage);
+                self.pc += 1;
+            }
+            0x2c => {
+                self.bit(AddressingMode::Absolute);
+                self.pc += 2;
+            }
+            0x30 => {
+                self.bmi();
+            }
+            0xd0 => {
+                self.bne();
+            }
+            0x10 => {
+                self.bpl();
+            }
+            0x50 => {
+                self.bvc();
+            }
+            0x70 => {
+                self.bvs();
+            }
+            0x18 => {
+                self.clc();
+            }
+            0xd8 => {
+                self.cld();
+            }
+            0x58 => {
+                self.cli();
+            }
+            0xb8 => {
+                self.clv();
+            }
+            0xc9 => {
+                self.cmp(AddressingMode::Immediate);
+                self.pc += 1;
+            }
+            0xc5 => {
+                self.cmp(AddressingMode::ZeroPage);
+                self.pc += 1;
+            }
+            0xd5 => {
+                self.cmp(AddressingMode::ZeroPageX);
+                self.pc += 1;
+            }
+            0xcd => {
+                self.cmp(AddressingMode::Absolute);
+                self.pc += 2;
+            }
+            0xdd => {
+                self.cmp(AddressingMode::AbsoluteX);
+                self.pc += 2;
+            }
+            0xd9 => {
+                self.cmp(AddressingMode::AbsoluteY);
+                self.pc += 2;
+            }
+            0xc1 => {
+                self.cmp(AddressingMode::IndirectX);
+                self.pc += 1;
+            }
+            0xd1 => {
+                self.cmp(AddressingMode::IndirectY);
+                self.pc += 1;
+            }
+            0xe0 => {
+                self.cpx(AddressingMode::Immediate);
+                self.pc += 1;
+            }
+            0xe4 => {
+                self.cpx(AddressingMode::ZeroPage);
+                self.pc += 1;
+            }
+            0xec => {
+                self.cpx(AddressingMode::Absolute);
+                self.pc += 2;
+            }
+            0xc0 => {
+                self.cpy(AddressingMode::Immediate);
+                self.pc += 1;
+            }
+            0xc4 => {
+                self.cpy(AddressingMode::Immediate);
+                self.pc += 1;
+            }
+            0xcc => {
+                self.cpy(AddressingMode::Absolute);
+                self.pc += 2;
+            }
+            0xc6 => {
+                self.dec(AddressingMode::ZeroPage);
+                self.pc += 1;
+            }
+            0xd6 => {
+                self.dec(AddressingMode::ZeroPageX);
+                self.pc += 1;
+            }
+            0xce => {
+                self.dec(AddressingMode::Absolute);
+                self.pc += 2;
+            }
+            0xde => {
+                self.dec(AddressingMode::AbsoluteX);
+                self.pc += 2;
+            }
+            0xca => {
+                self.dex();
+            }
+            0x88 => {
+                self.dey();
+            }
+            0x49 => {
+                self.eor(AddressingMode::Immediate);
+                self.pc += 1;
+            }
+            0x45 => {
+                self.eor(AddressingMode::ZeroPage);
+                self.pc += 1;
+            }
+            0x55 => {
+                self.eor(AddressingMode::ZeroPageX);
+                self.pc += 1;
+            }
+            0x4d => {
+                self.eor(AddressingMode::Absolute);
+                self.pc += 2;
+            }
+            0x5d => {
+                self.eor(AddressingMode::AbsoluteX);
+                self.pc += 2;
+            }
+            0x59 => {
+                self.eor(AddressingMode::AbsoluteY);
+                self.pc += 2;
+            }
+            0x41 => {
+                self.eor(AddressingMode::IndirectX);
+                self.pc += 1;
+            }
+            0x51 => {
+                self.eor(AddressingMode::IndirectY);
+                self.pc += 1;
+            }
+            0xe6 => {
+                self.inc(AddressingMode::ZeroPage);
+                self.pc += 1;
+            }
+            0xf6 => {
+                self.inc(AddressingMode::ZeroPageX);
+                self.pc += 1;
+            }
+            0xee => {
+                self.inc(AddressingMode::Absolute);
+                self.pc += 2;
+            }
+            0xfe => {
+                self.inc(AddressingMode::AbsoluteX);
+                self.pc += 2;
+            }
+            0xe8 => self.inx(),
+            0xc8 => self.iny(),
+            0x4c => {
+                self.jmp_absolute();
+            }
+            0x6c => {
+                self.jmp_indirect();
+            }
+            0x20 => {
+                self.jsr();
+            }
+            0xa9 => {
+                self.lda(AddressingMode::Immediate);
+                self.pc += 1;
+            }
+            0xa5 => {
+                self.lda(AddressingMode::ZeroPage);
+                self.pc += 1;
+            }
+            0xb5 => {
+                self.lda(AddressingMode::ZeroPageX);
+                self.pc += 1;
+            }
+            0xad => {
+                self.lda(AddressingMode::Absolute);
+                self.pc += 2;
+            }
+            0xbd => {
+                self.lda(AddressingMode::AbsoluteX);
+                self.pc += 2;
+            }
+            0xb9 => {
+                self.lda(AddressingMode::AbsoluteY);
+                self.pc += 2;
+            }
+            0xa1 => {
+                self.lda(AddressingMode::IndirectX);
+                self.pc += 1;
+            }
+            0xb1 => {
+                self.lda(AddressingMode::IndirectY);
+                self.pc += 1;
+            }
+            0xa2 => {
+                self.ldx(AddressingMode::Immediate);
+                self.pc += 1;
+            }
+            0xa6 => {
+                self.ldx(AddressingMode::ZeroPage);
+                self.pc += 1;
+            }
+            0xb6 => {
+                self.ldx(AddressingMode::ZeroPageY);
+                self.pc += 1;
+            }
+            0xae => {
+                self.ldx(AddressingMode::Absolute);
+                self.pc += 2;
+            }
+            0xbe => {
+                self.ldx(AddressingMode::AbsoluteY);
+                self.pc += 2;
+            }
+            0xa0 => {
+                self.ldy(AddressingMode::Immediate);
+                self.pc += 1;
+            }
+            0xa4 => {
+                self.ldy(AddressingMode::ZeroPage);
+                self.pc += 1;
+            }
+            0xb4 => {
+                self.ldy(AddressingMode::ZeroPageX);
+                self.pc += 1;
+            }
+            0xac => {
+                self.ldy(AddressingMode::Absolute);
+                self.pc += 2;
+            }
+            0xbc => {
+                self.ldy(AddressingMode::AbsoluteX);
+                self.pc += 2;
+            }
+            0x4a => {
+                self.lsr_accumulator();
+            }
+            0x46 => {
+                self.lsr(AddressingMode::ZeroPage);
+                self.pc += 1;
+            }
+            0x56 => {
+                self.lsr(AddressingMode::ZeroPageX);
+                self.pc += 1;
+            }
+            0x4e => {
+                self.lsr(AddressingMode::Absolute);
+                self.pc += 2;
+            }
+            0x5e => {
+                self.lsr(AddressingMode::AbsoluteX);
+                self.pc += 2;
+            }
+            0x09 => {
+                self.ora(AddressingMode::Immediate);
+                self.pc += 1;
+            }
+            0x05 => {
+                self.ora(AddressingMode::ZeroPage);
+                self.pc += 1;
+            }
+            0x15 => {
+                self.ora(AddressingMode::ZeroPageX);
+                self.pc += 1;
+            }
+            0x0d => {
+                self.ora(AddressingMode::Absolute);
+                self.pc += 2;
+            }
+            0x1d => {
+                self.ora(AddressingMode::AbsoluteX);
+                self.pc += 2;
+            }
+            0x19 => {
+                self.ora(AddressingMode::AbsoluteY);
+                self.pc += 2;
+            }
+            0x01 => {
+                self.ora(AddressingMode::IndirectX);
+                self.pc += 1;
+            }
+            0x11 => {
+                self.ora(AddressingMode::IndirectY);
+                self.pc += 1;
+            }
+            0x48 => {
+                self.pha();
+            }
+            0x08 => {
+                self.php();
+            }
+            0x68 => {
+                self.pla();
+            }
+            0x28 => {
+                self.plp();
+            }
+            0x2a => {
+                self.rol_accumulator();
+            }
+            0x26 => {
+                self.rol(AddressingMode::ZeroPage);
+                self.pc += 1;
+            }
+            0x36 => {
+                self.rol(AddressingMode::ZeroPageX);
+                self.pc += 1;
+            }
+            0x2e => {
+                self.rol(AddressingMode::Absolute);
+                self.pc += 2;
+            }
+            0x3e => {
+                self.rol(AddressingMode::AbsoluteX);
+                self.pc += 2;
+            }
+            0x6a => {
+                self.ror_accumulator();
+            }
+            0x66 => {
+                self.ror(AddressingMode::ZeroPage);
+                self.pc += 1;
+            }
+            0x76 => {
+                self.ror(AddressingMode::ZeroPageX);
+                self.pc += 1;
+            }
+            0x6e => {
+                self.ror(AddressingMode::Absolute);
+                self.pc += 2;
+            }
+            0x7e => {
+                self.ror(AddressingMode::AbsoluteX);
+                self.pc += 2;
+            }
+            0x40 => {
+                self.rti();
+            }
+            0x60 => {
+                self.rts();
+            }
+            0xe9 => {
+                self.sbc(AddressingMode::Immediate);
+                self.pc += 1;
+            }
+            0xe5 => {
+                self.sbc(AddressingMode::ZeroPage);
+                self.pc += 1;
+            }
+            0xf5 => {
+                self.sbc(AddressingMode::ZeroPageX);
+                self.pc += 1;
+            }
+            0xed => {
+                self.sbc(AddressingMode::Absolute);
+                self.pc += 2;
+            }
+            0xfd => {
+                self.sbc(AddressingMode::AbsoluteX);
+                self.pc += 2;
+            }
+            0xf9 => {
+                self.sbc(AddressingMode::AbsoluteY);
+                self.pc += 2;
+            }
+            0xe1 => {
+                self.sbc(AddressingMode::IndirectX);
+                self.pc += 1;
+            }
+            0xf1 => {
+                self.sbc(AddressingMode::IndirectY);
+                self.pc += 1;
+            }
+            0x38 => {
+                self.sec();
+            }
+            0xf8 => {
+                self.sed();
+            }
+            0x78 => {
+                self.sei();
+            }
+            0x85 => {
+                self.sta(AddressingMode::ZeroPage);
+                self.pc += 1
+            }
+            0x95 => {
+                self.sta(AddressingMode::ZeroPageX);
+                self.pc += 1
+            }
+            0x8d => {
+                self.sta(AddressingMode::Absolute);
+                self.pc += 2
+            }
+            0x9d => {
+                self.sta(AddressingMode::AbsoluteX);
+                self.pc += 2;
+            }
+            0x99 => {
+                self.sta(AddressingMode::AbsoluteY);
+                self.pc += 2;
+            }
+            0x81 => {
+                self.sta(AddressingMode::IndirectX);
+                self.pc += 1;
+            }
+            0x91 => {
+                self.sta(AddressingMode::IndirectY);
+                self.pc += 1;
+            }
+            0x86 => {
+                self.stx(AddressingMode::ZeroPage);
+                self.pc += 1;
+            }
+            0x96 => {
+                self.stx(AddressingMode::ZeroPageY);
+                self.pc += 1;
+            }
+            0x8e => {
+                self.stx(AddressingMode::Absolute);
+                self.pc += 2;
+            }
+            0x84 => {
+                self.sty(AddressingMode::ZeroPage);
+                self.pc += 1;
+            }
+            0x94 => {
+                self.sty(AddressingMode::ZeroPageX);
+                self.pc += 1;
+            }
+            0x8c => {
+                self.sty(AddressingMode::Absolute);
+                self.pc += 2;
+            }
+            0xaa => self.tax(),
+
+            0xa8 => self.tay(),
+
+            0xba => self.tsx(),
+
+            0x8a => self.txa(),
+
+            0x9a => self.txs(),
+
+            0x98 => self.tya(),
+
+            0xea => {
+
+            }
+
+            0x00 => {
+                self.cycles += op.cycles as u64;
+                self.accesses = None;
+                return Some(StopReason::Break);
+            }
+            _ => unreachable!("opcode {:02x} is in the table but not implemented", opscode),
+        }
+
+        self.cycles += op.cycles as u64 + self.extra_cycles as u64;
+        if self.page_crossed && opcodes::has_page_penalty(op) {
+            self.cycles += 1;
+        }
+        if self.bus.take_dma() {
+            // a halt cycle, another if the write was on an odd cycle to
+            // line up with a read, then 256 read/write pairs
+            self.cycles += 513 + (self.cycles - 1) % 2;
+        }
+        let ticked = self.accesses.take().map_or(0, |accesses| accesses.saturating_sub(1));
+        self.bus.tick((self.cycles - start).saturating_sub(ticked));
+        if self.bus.take_nmi() {
+            let start = self.cycles;
+            self.interrupt(NMI_VECTOR);
+            self.bus.tick(self.cycles - start);
+        }
+
+        if self.sanitizer.as_ref().is_some_and(Sanitizer::has_errors) {
+            return Some(StopReason::Sanitizer);
+        }
+        None
+    }
+}
+
+// Bounded execution for embedders, tests and frontends.
+impl CPU {
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn set_frame_cycles(&mut self, cycles: u64) {
+        self.frame_cycles = cycles;
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    /// Runs until `done` returns a reason to stop, checked before every
+    /// instruction. A breakpoint on the first instruction is stepped over so
+    /// that a stopped run can be resumed.
+    pub fn run_while<F>(&mut self, mut done: F) -> StopReason where F: FnMut(&CPU) -> Option<StopReason> {
+        let mut first = true;
+        loop {
+            if let Some(reason) = done(self) {
+                return reason;
+            }
+            if !first && self.breakpoints.contains(&self.pc) {
+                return StopReason::Breakpoint(self.pc);
+            }
+            first = false;
+
+            if let Some(reason) = self.step() {
+                return reason;
+            }
+        }
+    }
+
+    /// Runs until at least `cycles` more cycles have elapsed. Instructions
+    /// are never split, so the budget may be overshot by a few cycles.
+    pub fn run_for_cycles(&mut self, cycles: u64) -> StopReason {
+        let target = self.cycles + cycles;
+        self.run_while(|cpu| (cpu.cycles >= target).then_some(StopReason::BudgetExhausted))
+    }
+
+    pub fn run_for_instructions(&mut self, count: u64) -> StopReason {
+        let mut remaining = count;
+        self.run_while(|_| {
+            if remaining == 0 {
+                return Some(StopReason::BudgetExhausted);
+            }
+            remaining -= 1;
+            None
+        })
+    }
+
+    pub fn run_until<P>(&mut self, mut predicate: P) -> StopReason where P: FnMut(&CPU) -> bool {
+        self.run_while(|cpu| predicate(cpu).then_some(StopReason::Condition))
+    }
+
+    /// Runs up to the next frame boundary, a multiple of `frame_cycles`
+    /// since power-on, so overshoot doesn't accumulate from frame to frame.
+    pub fn run_frame(&mut self) -> StopReason {
+        let target = (self.cycles / self.frame_cycles + 1) * self.frame_cycles;
+        self.run_while(|cpu| (cpu.cycles >= target).then_some(StopReason::BudgetExhausted))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::loader::{Format, Segment};
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_adc_from_memory() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0x69, 0x13, 0x00]);
+        println!("{}",cpu.ra);
+        assert_eq!(cpu.ra, 0x13);
+    }
+
+    #[test]
+    fn test_lda_immediate() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xA9, 0x17, 0x00]);
+        assert_eq!(cpu.ra, 0x17);
+    }
+
+    #[test]
+    fn test_lda_from_memory() {
+        let mut cpu = CPU::new();
+        cpu.mem_write(0x10, 0x55);
+        cpu.load_and_run(vec![0xa5, 0x10, 0x00]);
+        assert_eq!(cpu.ra, 0x55);
+    }
+
+
+    #[test]
+    fn test_0xa9_lda_immidiate_load_data() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xa9, 0x05, 0x00]);
+        assert_eq!(cpu.ra, 5);
+        assert!(!cpu.rp.contains(ProcessorStatus::ZERO));
+        assert!(!cpu.rp.contains(ProcessorStatus::NEGATIVE));
+    }
+
+    #[test]
+    fn test_0xaa_tax_move_a_to_x() {
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xaa, 0x00]).unwrap();
+        cpu.reset();
+        cpu.ra = 10;
+        cpu.run();
+
+        assert_eq!(cpu.rx, 10)
+    }
+
+    #[test]
+    fn test_5_ops_working_together() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xa9, 0xc0, 0xaa, 0xe8, 0x00]);
+
+        assert_eq!(cpu.rx, 0xc1)
+    }
+
+    #[test]
+    fn test_inx_overflow() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xe8, 0xe8, 0x00]);
+        assert_eq!(cpu.rx, 2);
+    }
+
+    fn cpu_with(program: Vec<u8>) -> CPU {
+        let mut cpu = CPU::new();
+        cpu.load(program).unwrap();
+        cpu.reset();
+        cpu
+    }
+
+    #[test]
+    fn test_cycle_counting() {
+        // LDX #$01; LDA $02ff,X (page cross); BNE +0 (taken); BRK
+        let mut cpu = cpu_with(vec![0xa2, 0x01, 0xbd, 0xff, 0x02, 0xd0, 0x00, 0x00]);
+        cpu.mem_write(0x0300, 0x01);
+        assert_eq!(cpu.run(), StopReason::Break);
+        assert_eq!(cpu.cycles(), 7 + 2 + 5 + 3 + 7);
+    }
+
+    #[test]
+    fn test_run_for_budgets() {
+        // INX; JMP $0600
+        let mut cpu = cpu_with(vec![0xe8, 0x4c, 0x00, 0x06]);
+        assert_eq!(cpu.run_for_instructions(5), StopReason::BudgetExhausted);
+        assert_eq!(cpu.rx, 3);
+        assert_eq!(cpu.run_for_cycles(10), StopReason::BudgetExhausted);
+        assert_eq!(cpu.cycles(), 7 + 3 * 2 + 2 * 3 + 10);
+        cpu.set_frame_cycles(100);
+        assert_eq!(cpu.run_frame(), StopReason::BudgetExhausted);
+        assert!((100..103).contains(&cpu.cycles()));
+        cpu.run_frame();
+        assert!((200..203).contains(&cpu.cycles()));
+    }
+
+    #[test]
+    fn test_run_until_and_breakpoints() {
+        // INX; JMP $0600
+        let mut cpu = cpu_with(vec![0xe8, 0x4c, 0x00, 0x06]);
+        assert_eq!(cpu.run_until(|cpu| cpu.rx == 4), StopReason::Condition);
+        cpu.add_breakpoint(0x0601);
+        assert_eq!(cpu.run_for_instructions(100), StopReason::Breakpoint(0x0601));
+        assert_eq!(cpu.rx, 5);
+        assert_eq!(cpu.run_for_instructions(100), StopReason::Breakpoint(0x0601));
+        assert_eq!(cpu.rx, 6);
+        cpu.remove_breakpoint(0x0601);
+        assert_eq!(cpu.run_for_instructions(2), StopReason::BudgetExhausted);
+    }
+
+    #[test]
+    fn test_jam_on_unofficial_opcode() {
+        let mut cpu = cpu_with(vec![0xe8, 0x02, 0x00]);
+        assert_eq!(cpu.run(), StopReason::Jam(0x02));
+        assert_eq!(cpu.pc, 0x0601);
+    }
+
+    #[test]
+    fn test_memory_mapped_device() {
+        let mut cpu = CPU::new();
+        let out = Rc::new(Cell::new(0));
+        let sink = out.clone();
+        cpu.bus.on_read(0xfe..=0xfe, |_| 0x2a);
+        cpu.bus.on_write(0xd012..=0xd012, move |_, val| sink.set(val));
+        // LDA $FE; STA $D012
+        cpu.load_and_run(vec![0xa5, 0xfe, 0x8d, 0x12, 0xd0, 0x00]);
+        assert_eq!(cpu.ra, 0x2a);
+        assert_eq!(out.get(), 0x2a);
+        assert_eq!(cpu.mem_peek(0xd012), 0);
+    }
+
+    #[test]
+    fn test_load_image() {
+        let mut cpu = CPU::new();
+        let image = Image::parse(Format::Prg, &[0x00, 0xc0, 0xa9, 0x42, 0x00], 0).unwrap();
+        cpu.load_image(&image, None).unwrap();
+        cpu.reset();
+        assert_eq!(cpu.pc, 0xc000);
+        cpu.run();
+        assert_eq!(cpu.ra, 0x42);
+
+        cpu.load_image(&Image::raw(&[0xe8, 0x00], 0x1000), Some(0x1000)).unwrap();
+        cpu.reset();
+        assert_eq!(cpu.pc, 0x1000);
+        assert_eq!(
+            cpu.load_image(&Image::raw(&[0; 0x10], 0xfff8), None),
+            Err(LoadError::OutOfBounds { addr: 0xfff8, len: 0x10 })
+        );
+    }
+
+    #[test]
+    fn test_image_keeps_its_own_reset_vector() {
+        let mut cpu = CPU::new();
+        let image = Image {
+            segments: vec![
+                Segment { addr: 0x8000, data: vec![0xe8, 0x00] },
+                Segment { addr: 0xfffc, data: vec![0x00, 0x80, 0x00, 0x00] },
+            ],
+            entry: None,
+        };
+        cpu.load_image(&image, None).unwrap();
+        cpu.reset();
+        assert_eq!(cpu.pc, 0x8000);
+    }
+}
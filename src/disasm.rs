@@ -0,0 +1,119 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::opcodes;
+use crate::symbols::SymbolTable;
+use crate::{AddressingMode, CPU};
+
+/// Disassembles the instruction at `addr`, returning its text and length.
+/// Unofficial opcodes come out as a `.db` byte.
+pub fn disassemble<F>(read: F, addr: u16, symbols: Option<&SymbolTable>) -> (String, u16)
+where
+    F: Fn(u16) -> u8,
+{
+    let code = read(addr);
+    let Some(op) = opcodes::lookup(code) else {
+        return (format!(".db ${:02x}", code), 1);
+    };
+    let lo = read(addr.wrapping_add(1));
+    let hi = read(addr.wrapping_add(2));
+    let word = u16::from_le_bytes([lo, hi]);
+    let name = |target: u16, digits: usize| match symbols.and_then(|s| s.name_at(target, None)) {
+        Some(name) => name.to_string(),
+        None => format!("${:0width$x}", target, width = digits),
+    };
+
+    let operand = match op.mode {
+        AddressingMode::Implied => String::new(),
+        AddressingMode::Accumulator => "A".to_string(),
+        AddressingMode::Immediate => format!("#${:02x}", lo),
+        AddressingMode::ZeroPage => name(lo as u16, 2),
+        AddressingMode::ZeroPageX => format!("{},X", name(lo as u16, 2)),
+        AddressingMode::ZeroPageY => format!("{},Y", name(lo as u16, 2)),
+        AddressingMode::Absolute => name(word, 4),
+        AddressingMode::AbsoluteX => format!("{},X", name(word, 4)),
+        AddressingMode::AbsoluteY => format!("{},Y", name(word, 4)),
+        AddressingMode::Indirect => format!("({})", name(word, 4)),
+        AddressingMode::IndirectX => format!("({},X)", name(lo as u16, 2)),
+        AddressingMode::IndirectY => format!("({}),Y", name(lo as u16, 2)),
+        AddressingMode::Relative => {
+            let target = addr.wrapping_add(2).wrapping_add(lo as i8 as u16);
+            name(target, 4)
+        }
+    };
+
+    let text = if operand.is_empty() {
+        op.mnemonic.to_string()
+    } else {
+        format!("{} {}", op.mnemonic, operand)
+    };
+    (text, op.len as u16)
+}
+
+/// One trace-log line for the instruction about to execute, in the
+/// familiar nestest layout.
+pub fn trace(cpu: &CPU, symbols: Option<&SymbolTable>) -> String {
+    let pc = cpu.pc;
+    let (text, len) = disassemble(|addr| cpu.mem_peek(addr), pc, symbols);
+    let bytes: Vec<String> = (0..len)
+        .map(|i| format!("{:02X}", cpu.mem_peek(pc.wrapping_add(i))))
+        .collect();
+    let text = match symbols.and_then(|s| s.name_at(pc, None)) {
+        Some(label) => format!("{}: {}", label, text),
+        None => text,
+    };
+    format!(
+        "{:04X}  {:<9} {:<32} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+        pc,
+        bytes.join(" "),
+        text,
+        cpu.ra,
+        cpu.rx,
+        cpu.ry,
+        cpu.rp.bits(),
+        cpu.rs,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn disassemble_bytes(bytes: &[u8], symbols: Option<&SymbolTable>) -> String {
+        let read = |addr: u16| bytes.get(addr as usize - 0x0600).copied().unwrap_or(0);
+        disassemble(read, 0x0600, symbols).0
+    }
+
+    #[test]
+    fn test_disassemble_modes() {
+        assert_eq!(disassemble_bytes(&[0xa9, 0x10], None), "LDA #$10");
+        assert_eq!(disassemble_bytes(&[0xb1, 0x00], None), "LDA ($00),Y");
+        assert_eq!(disassemble_bytes(&[0x9d, 0x00, 0x02], None), "STA $0200,X");
+        assert_eq!(disassemble_bytes(&[0xd0, 0xfb], None), "BNE $05fd");
+        assert_eq!(disassemble_bytes(&[0x0a], None), "ASL A");
+        assert_eq!(disassemble_bytes(&[0x02], None), ".db $02");
+    }
+
+    #[test]
+    fn test_disassemble_with_symbols() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert("loop", 0x05fd, None);
+        symbols.insert("draw", 0x0735, None);
+        assert_eq!(disassemble_bytes(&[0xd0, 0xfb], Some(&symbols)), "BNE loop");
+        assert_eq!(disassemble_bytes(&[0x20, 0x35, 0x07], Some(&symbols)), "JSR draw");
+    }
+
+    #[test]
+    fn test_trace_line() {
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xa9, 0x10, 0x00]).unwrap();
+        cpu.reset();
+        let mut symbols = SymbolTable::new();
+        symbols.insert("init", 0x0600, None);
+        assert_eq!(
+            trace(&cpu, Some(&symbols)),
+            "0600  A9 10     init: LDA #$10                   A:00 X:00 Y:00 P:24 SP:FD"
+        );
+    }
+}
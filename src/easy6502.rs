@@ -0,0 +1,131 @@
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use core::cell::{Cell, RefCell, RefMut};
+
+use crate::framebuffer::Framebuffer;
+use crate::random::RandomSource;
+use crate::CPU;
+
+pub const WIDTH: usize = 32;
+pub const HEIGHT: usize = 32;
+/// Where programs go unless told otherwise.
+pub const LOAD_ADDR: u16 = 0x0600;
+const SCREEN: u16 = 0x0200;
+const RANDOM: u16 = 0xfe;
+const KEY: u16 = 0xff;
+
+/// The machine from Nick Morgan's easy6502 tutorial: a 32×32 screen of
+/// palette indices at $0200-$05FF, a random byte at $FE and the ASCII
+/// code of the last key pressed at $FF.
+pub struct Easy6502 {
+    key: Rc<Cell<u8>>,
+    screen: Rc<RefCell<Framebuffer>>,
+}
+
+impl Easy6502 {
+    /// Wires the devices onto `cpu`'s bus, taking $FE from `random`; the
+    /// program is loaded separately.
+    pub fn attach(cpu: &mut CPU, mut random: Box<dyn RandomSource>) -> Easy6502 {
+        cpu.bus.on_read(RANDOM..=RANDOM, move |_| random.next_byte());
+        let key = Rc::new(Cell::new(0));
+        let latch = key.clone();
+        cpu.bus.on_read(KEY..=KEY, move |_| latch.get());
+        let latch = key.clone();
+        cpu.bus.on_write(KEY..=KEY, move |_, val| latch.set(val));
+        let screen = Rc::new(RefCell::new(Framebuffer::new(WIDTH, HEIGHT)));
+        let fb = screen.clone();
+        let end = SCREEN + (WIDTH * HEIGHT) as u16 - 1;
+        cpu.bus.watch(SCREEN..=end, move |addr, val| {
+            let cell = (addr - SCREEN) as usize;
+            fb.borrow_mut().set_pixel(cell % WIDTH, cell / WIDTH, color(val));
+        });
+        Easy6502 { key, screen }
+    }
+
+    pub fn key_down(&self, ascii: u8) {
+        self.key.set(ascii);
+    }
+
+    /// Clears $FF, for programs that poll for a key being held.
+    pub fn key_up(&self) {
+        self.key.set(0);
+    }
+
+    /// The screen, kept up to date as the program writes to it.
+    pub fn screen(&self) -> RefMut<'_, Framebuffer> {
+        self.screen.borrow_mut()
+    }
+}
+
+/// The RGB a screen cell is drawn in. Only the low nibble counts, as in
+/// easy6502, so any random byte is a colour.
+pub fn color(byte: u8) -> [u8; 3] {
+    match byte & 0x0f {
+        0 => [0x00, 0x00, 0x00],
+        1 => [0xff, 0xff, 0xff],
+        2 | 9 => [0x80, 0x80, 0x80],
+        3 | 10 => [0xff, 0x00, 0x00],
+        4 | 11 => [0x00, 0xff, 0x00],
+        5 | 12 => [0x00, 0x00, 0xff],
+        6 | 13 => [0xff, 0x00, 0xff],
+        7 | 14 => [0xff, 0xff, 0x00],
+        _ => [0x00, 0xff, 0xff],
+    }
+}
+
+/// The snake game from the easy6502 tutorial, assembled for `LOAD_ADDR`.
+pub const SNAKE: &[u8] = &[
+    0x20, 0x06, 0x06, 0x20, 0x38, 0x06, 0x20, 0x0d, 0x06, 0x20, 0x2a, 0x06, 0x60, 0xa9, 0x02,
+    0x85, 0x02, 0xa9, 0x04, 0x85, 0x03, 0xa9, 0x11, 0x85, 0x10, 0xa9, 0x10, 0x85, 0x12, 0xa9,
+    0x0f, 0x85, 0x14, 0xa9, 0x04, 0x85, 0x11, 0x85, 0x13, 0x85, 0x15, 0x60, 0xa5, 0xfe, 0x85,
+    0x00, 0xa5, 0xfe, 0x29, 0x03, 0x18, 0x69, 0x02, 0x85, 0x01, 0x60, 0x20, 0x4d, 0x06, 0x20,
+    0x8d, 0x06, 0x20, 0xc3, 0x06, 0x20, 0x19, 0x07, 0x20, 0x20, 0x07, 0x20, 0x2d, 0x07, 0x4c,
+    0x38, 0x06, 0xa5, 0xff, 0xc9, 0x77, 0xf0, 0x0d, 0xc9, 0x64, 0xf0, 0x14, 0xc9, 0x73, 0xf0,
+    0x1b, 0xc9, 0x61, 0xf0, 0x22, 0x60, 0xa9, 0x04, 0x24, 0x02, 0xd0, 0x26, 0xa9, 0x01, 0x85,
+    0x02, 0x60, 0xa9, 0x08, 0x24, 0x02, 0xd0, 0x1b, 0xa9, 0x02, 0x85, 0x02, 0x60, 0xa9, 0x01,
+    0x24, 0x02, 0xd0, 0x10, 0xa9, 0x04, 0x85, 0x02, 0x60, 0xa9, 0x02, 0x24, 0x02, 0xd0, 0x05,
+    0xa9, 0x08, 0x85, 0x02, 0x60, 0x60, 0x20, 0x94, 0x06, 0x20, 0xa8, 0x06, 0x60, 0xa5, 0x00,
+    0xc5, 0x10, 0xd0, 0x0d, 0xa5, 0x01, 0xc5, 0x11, 0xd0, 0x07, 0xe6, 0x03, 0xe6, 0x03, 0x20,
+    0x2a, 0x06, 0x60, 0xa2, 0x02, 0xb5, 0x10, 0xc5, 0x10, 0xd0, 0x06, 0xb5, 0x11, 0xc5, 0x11,
+    0xf0, 0x09, 0xe8, 0xe8, 0xe4, 0x03, 0xf0, 0x06, 0x4c, 0xaa, 0x06, 0x4c, 0x35, 0x07, 0x60,
+    0xa6, 0x03, 0xca, 0x8a, 0xb5, 0x10, 0x95, 0x12, 0xca, 0x10, 0xf9, 0xa5, 0x02, 0x4a, 0xb0,
+    0x09, 0x4a, 0xb0, 0x19, 0x4a, 0xb0, 0x1f, 0x4a, 0xb0, 0x2f, 0xa5, 0x10, 0x38, 0xe9, 0x20,
+    0x85, 0x10, 0x90, 0x01, 0x60, 0xc6, 0x11, 0xa9, 0x01, 0xc5, 0x11, 0xf0, 0x28, 0x60, 0xe6,
+    0x10, 0xa9, 0x1f, 0x24, 0x10, 0xf0, 0x1f, 0x60, 0xa5, 0x10, 0x18, 0x69, 0x20, 0x85, 0x10,
+    0xb0, 0x01, 0x60, 0xe6, 0x11, 0xa9, 0x06, 0xc5, 0x11, 0xf0, 0x0c, 0x60, 0xc6, 0x10, 0xa5,
+    0x10, 0x29, 0x1f, 0xc9, 0x1f, 0xf0, 0x01, 0x60, 0x4c, 0x35, 0x07, 0xa0, 0x00, 0xa5, 0xfe,
+    0x91, 0x00, 0x60, 0xa6, 0x03, 0xa9, 0x00, 0x81, 0x10, 0xa2, 0x00, 0xa9, 0x01, 0x81, 0x10,
+    0x60, 0xa6, 0xff, 0xea, 0xea, 0xca, 0xd0, 0xfb, 0x60,
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::random::Scripted;
+
+    #[test]
+    fn test_devices_and_screen() {
+        let mut cpu = CPU::new();
+        let machine = Easy6502::attach(&mut cpu, Box::new(Scripted::new(vec![0x05])));
+        // LDA $FF; STA $0200; LDA $FE; STA $0201
+        cpu.load(vec![0xa5, 0xff, 0x8d, 0x00, 0x02, 0xa5, 0xfe, 0x8d, 0x01, 0x02, 0x00]).unwrap();
+        cpu.reset();
+        machine.key_down(0x03);
+        cpu.run();
+
+        let mut screen = machine.screen();
+        assert_eq!(screen.take_dirty_rows(), Some(0..1));
+        assert_eq!(screen.pixels()[..3], [0xff, 0x00, 0x00]);
+        assert_eq!(cpu.mem_peek(0x0201), 0x05);
+        assert_eq!(screen.take_dirty_rows(), None);
+        drop(screen);
+        machine.key_up();
+        assert_eq!(cpu.mem_read(0xff), 0);
+    }
+
+    #[test]
+    fn test_colors_ignore_the_high_nibble() {
+        assert_eq!(color(0xf3), color(0x03));
+        assert_eq!(color(0x10), color(0x00));
+    }
+}
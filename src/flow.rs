@@ -0,0 +1,505 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::vec;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use crate::disasm;
+use crate::opcodes::{self, OpCode};
+use crate::symbols::SymbolTable;
+use crate::{AddressingMode, StopReason, CPU};
+
+const NMI_VECTOR: u16 = 0xfffa;
+const RESET_VECTOR: u16 = 0xfffc;
+const IRQ_VECTOR: u16 = 0xfffe;
+
+/// Targets of `JMP ($xxxx)` seen while the program was running, keyed by
+/// the address of the jump.
+#[derive(Default)]
+pub struct IndirectJumps {
+    targets: BTreeMap<u16, BTreeSet<u16>>,
+}
+
+impl IndirectJumps {
+    pub fn new() -> IndirectJumps {
+        IndirectJumps::default()
+    }
+
+    pub fn insert(&mut self, site: u16, target: u16) {
+        self.targets.entry(site).or_default().insert(target);
+    }
+
+    /// Runs `cpu` for `cycles`, or until it stops, recording every
+    /// indirect jump it takes.
+    pub fn observe(cpu: &mut CPU, cycles: u64) -> IndirectJumps {
+        let mut indirect = IndirectJumps::new();
+        let target = cpu.cycles() + cycles;
+        cpu.run_while(|cpu| {
+            indirect.record(cpu);
+            (cpu.cycles() >= target).then_some(StopReason::BudgetExhausted)
+        });
+        indirect
+    }
+
+    /// Records the target if `cpu` is about to execute an indirect jump.
+    pub fn record(&mut self, cpu: &CPU) {
+        if cpu.mem_peek(cpu.pc) != 0x6c {
+            return;
+        }
+        let ptr = u16::from_le_bytes([cpu.mem_peek(cpu.pc.wrapping_add(1)), cpu.mem_peek(cpu.pc.wrapping_add(2))]);
+        // same page-wrap bug as jmp_indirect
+        let hi_ptr = (ptr & 0xff00) | (ptr.wrapping_add(1) & 0x00ff);
+        let target = u16::from_le_bytes([cpu.mem_peek(ptr), cpu.mem_peek(hi_ptr)]);
+        self.insert(cpu.pc, target);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BasicBlock {
+    pub start: u16,
+    /// Start addresses of the instructions in the block, in order.
+    pub instructions: Vec<u16>,
+    pub successors: Vec<u16>,
+    /// Subroutines entered by JSRs inside the block.
+    pub calls: Vec<u16>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub entry: u16,
+    pub blocks: BTreeSet<u16>,
+}
+
+pub struct ControlFlowGraph {
+    pub blocks: BTreeMap<u16, BasicBlock>,
+    pub functions: BTreeMap<u16, Function>,
+    /// Indirect jumps with no recorded target.
+    pub unresolved: BTreeSet<u16>,
+    text: BTreeMap<u16, String>,
+}
+
+/// Walks code reachable from a set of entry points and splits it into
+/// basic blocks and functions.
+pub struct FlowAnalyzer<F: Fn(u16) -> u8> {
+    read: F,
+    entries: BTreeSet<u16>,
+    cdl: Option<(Vec<u16>, Vec<u8>)>,
+    indirect: IndirectJumps,
+}
+
+enum Flow {
+    Next,
+    Branch(u16),
+    Jump(u16),
+    Call(u16),
+    Indirect,
+    Stop,
+}
+
+impl<F: Fn(u16) -> u8> FlowAnalyzer<F> {
+    pub fn new(read: F) -> Self {
+        FlowAnalyzer {
+            read,
+            entries: BTreeSet::new(),
+            cdl: None,
+            indirect: IndirectJumps::new(),
+        }
+    }
+
+    pub fn add_entry(&mut self, addr: u16) {
+        self.entries.insert(addr);
+    }
+
+    /// Adds the reset, NMI and IRQ handlers as entry points. Vectors
+    /// still at $0000 are taken to be unset.
+    pub fn add_vectors(&mut self) {
+        for vector in [RESET_VECTOR, NMI_VECTOR, IRQ_VECTOR] {
+            let addr = u16::from_le_bytes([(self.read)(vector), (self.read)(vector.wrapping_add(1))]);
+            if addr != 0 {
+                self.entries.insert(addr);
+            }
+        }
+    }
+
+    /// Code/data log in FCEUX `.cdl` layout: a byte for each of the
+    /// `prg_len` bytes of PRG-ROM, then CHR-ROM's, which are ignored. The
+    /// CPU sees PRG-ROM at each of `bases`. Bytes logged as code that the
+    /// walk doesn't reach become extra entry points, in every mirror.
+    pub fn set_cdl(&mut self, mut cdl: Vec<u8>, prg_len: usize, bases: &[u16]) {
+        cdl.truncate(prg_len);
+        self.cdl = Some((bases.to_vec(), cdl));
+    }
+
+    pub fn set_indirect_jumps(&mut self, indirect: IndirectJumps) {
+        self.indirect = indirect;
+    }
+
+    fn decode(&self, addr: u16) -> Option<&'static OpCode> {
+        opcodes::lookup((self.read)(addr))
+    }
+
+    fn flow(&self, addr: u16, op: &OpCode) -> Flow {
+        let lo = (self.read)(addr.wrapping_add(1));
+        let hi = (self.read)(addr.wrapping_add(2));
+        match (op.code, op.mode) {
+            (0x00, _) | (0x40, _) | (0x60, _) => Flow::Stop,
+            (0x4c, _) => Flow::Jump(u16::from_le_bytes([lo, hi])),
+            (0x6c, _) => Flow::Indirect,
+            (0x20, _) => Flow::Call(u16::from_le_bytes([lo, hi])),
+            (_, AddressingMode::Relative) => Flow::Branch(addr.wrapping_add(2).wrapping_add(lo as i8 as u16)),
+            _ => Flow::Next,
+        }
+    }
+
+    pub fn analyze(&self) -> ControlFlowGraph {
+        let mut decoded: BTreeMap<u16, &'static OpCode> = BTreeMap::new();
+        let mut leaders: BTreeSet<u16> = self.entries.clone();
+        let mut function_entries: BTreeSet<u16> = self.entries.clone();
+        let mut unresolved = BTreeSet::new();
+        let mut covered = vec![false; 0x10000];
+
+        let mut pending: Vec<u16> = self.entries.iter().rev().copied().collect();
+        let mut cdl_roots: Vec<u16> = match &self.cdl {
+            Some((bases, cdl)) => {
+                let code = cdl.iter().enumerate().filter(|(_, &flags)| flags & 0x01 != 0);
+                let mut roots: Vec<u16> = code
+                    .flat_map(|(i, _)| bases.iter().filter_map(move |&base| u16::try_from(base as usize + i).ok()))
+                    .collect();
+                roots.sort_unstable_by(|a, b| b.cmp(a));
+                roots.dedup();
+                roots
+            }
+            None => Vec::new(),
+        };
+
+        loop {
+            let Some(addr) = pending.pop() else {
+                // only fall back to the code log once the walk is exhausted,
+                // so operand bytes it marks aren't mistaken for opcodes
+                match cdl_roots.pop() {
+                    Some(addr) if !covered[addr as usize] => {
+                        leaders.insert(addr);
+                        function_entries.insert(addr);
+                        pending.push(addr);
+                    }
+                    Some(_) => {}
+                    None => break,
+                }
+                continue;
+            };
+            if decoded.contains_key(&addr) {
+                continue;
+            }
+            let Some(op) = self.decode(addr) else {
+                continue;
+            };
+            decoded.insert(addr, op);
+            for i in 0..op.len as u16 {
+                covered[addr.wrapping_add(i) as usize] = true;
+            }
+            let next = addr.wrapping_add(op.len as u16);
+            match self.flow(addr, op) {
+                Flow::Next => pending.push(next),
+                Flow::Branch(target) => {
+                    leaders.insert(target);
+                    leaders.insert(next);
+                    pending.push(next);
+                    pending.push(target);
+                }
+                Flow::Jump(target) => {
+                    leaders.insert(target);
+                    pending.push(target);
+                }
+                Flow::Call(target) => {
+                    leaders.insert(target);
+                    function_entries.insert(target);
+                    pending.push(next);
+                    pending.push(target);
+                }
+                Flow::Indirect => match self.indirect.targets.get(&addr) {
+                    Some(targets) => {
+                        for &target in targets {
+                            leaders.insert(target);
+                            pending.push(target);
+                        }
+                    }
+                    None => {
+                        unresolved.insert(addr);
+                    }
+                },
+                Flow::Stop => {}
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        for &leader in leaders.iter().filter(|addr| decoded.contains_key(addr)) {
+            let mut block = BasicBlock {
+                start: leader,
+                instructions: Vec::new(),
+                successors: Vec::new(),
+                calls: Vec::new(),
+            };
+            let mut addr = leader;
+            loop {
+                let op = decoded[&addr];
+                block.instructions.push(addr);
+                let next = addr.wrapping_add(op.len as u16);
+                let falls_through = match self.flow(addr, op) {
+                    Flow::Next => true,
+                    Flow::Call(target) => {
+                        block.calls.push(target);
+                        true
+                    }
+                    Flow::Branch(target) => {
+                        block.successors.push(target);
+                        block.successors.push(next);
+                        false
+                    }
+                    Flow::Jump(target) => {
+                        block.successors.push(target);
+                        false
+                    }
+                    Flow::Indirect => {
+                        if let Some(targets) = self.indirect.targets.get(&addr) {
+                            block.successors.extend(targets);
+                        }
+                        false
+                    }
+                    Flow::Stop => false,
+                };
+                if !falls_through || !decoded.contains_key(&next) {
+                    break;
+                }
+                if leaders.contains(&next) {
+                    block.successors.push(next);
+                    break;
+                }
+                addr = next;
+            }
+            block.successors.retain(|target| decoded.contains_key(target));
+            block.successors.dedup();
+            blocks.insert(leader, block);
+        }
+
+        let mut functions = BTreeMap::new();
+        for &entry in function_entries.iter().filter(|addr| blocks.contains_key(addr)) {
+            let mut members = BTreeSet::new();
+            let mut stack = vec![entry];
+            while let Some(start) = stack.pop() {
+                if members.insert(start) {
+                    stack.extend(&blocks[&start].successors);
+                }
+            }
+            functions.insert(entry, Function { entry, blocks: members });
+        }
+
+        let text = decoded
+            .keys()
+            .map(|&addr| (addr, disasm::disassemble(&self.read, addr, None).0))
+            .collect();
+
+        ControlFlowGraph { blocks, functions, unresolved, text }
+    }
+}
+
+impl ControlFlowGraph {
+    fn name(addr: u16, symbols: Option<&SymbolTable>) -> String {
+        match symbols.and_then(|s| s.name_at(addr, None)) {
+            Some(name) => name.to_string(),
+            None => format!("${:04x}", addr),
+        }
+    }
+
+    fn block_text(&self, block: &BasicBlock) -> Vec<String> {
+        block
+            .instructions
+            .iter()
+            .map(|addr| format!("{:04x}  {}", addr, self.text[addr]))
+            .collect()
+    }
+
+    /// Graphviz rendering: one node per basic block, solid edges for
+    /// jumps/branches/fall-through and dashed edges for calls.
+    pub fn to_dot(&self, symbols: Option<&SymbolTable>) -> String {
+        let mut out = String::new();
+        writeln!(out, "digraph cfg {{").unwrap();
+        writeln!(out, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            if self.functions.contains_key(&block.start) || symbols.and_then(|s| s.name_at(block.start, None)).is_some() {
+                label.push_str(&dot_escape(&Self::name(block.start, symbols)));
+                label.push_str(":\\l");
+            }
+            for line in self.block_text(block) {
+                label.push_str(&dot_escape(&line));
+                label.push_str("\\l");
+            }
+            let shape = if self.functions.contains_key(&block.start) { ", peripheries=2" } else { "" };
+            writeln!(out, "    b{:04x} [label=\"{}\"{}];", block.start, label, shape).unwrap();
+        }
+        for block in self.blocks.values() {
+            for target in &block.successors {
+                writeln!(out, "    b{:04x} -> b{:04x};", block.start, target).unwrap();
+            }
+            for target in block.calls.iter().filter(|target| self.blocks.contains_key(target)) {
+                writeln!(out, "    b{:04x} -> b{:04x} [style=dashed];", block.start, target).unwrap();
+            }
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+
+    pub fn to_json(&self, symbols: Option<&SymbolTable>) -> String {
+        let list = |addrs: &mut dyn Iterator<Item = &u16>| {
+            addrs.map(|addr| addr.to_string()).collect::<Vec<_>>().join(", ")
+        };
+        let mut out = String::new();
+        writeln!(out, "{{").unwrap();
+        writeln!(out, "  \"blocks\": [").unwrap();
+        for (i, block) in self.blocks.values().enumerate() {
+            let instructions: Vec<String> = self.block_text(block).iter().map(|line| json_string(line)).collect();
+            write!(
+                out,
+                "    {{\"start\": {}, \"instructions\": [{}], \"successors\": [{}], \"calls\": [{}]}}",
+                block.start,
+                instructions.join(", "),
+                list(&mut block.successors.iter()),
+                list(&mut block.calls.iter()),
+            )
+            .unwrap();
+            writeln!(out, "{}", if i + 1 < self.blocks.len() { "," } else { "" }).unwrap();
+        }
+        writeln!(out, "  ],").unwrap();
+        writeln!(out, "  \"functions\": [").unwrap();
+        for (i, function) in self.functions.values().enumerate() {
+            write!(
+                out,
+                "    {{\"entry\": {}, \"name\": {}, \"blocks\": [{}]}}",
+                function.entry,
+                json_string(&Self::name(function.entry, symbols)),
+                list(&mut function.blocks.iter()),
+            )
+            .unwrap();
+            writeln!(out, "{}", if i + 1 < self.functions.len() { "," } else { "" }).unwrap();
+        }
+        writeln!(out, "  ],").unwrap();
+        writeln!(out, "  \"unresolved\": [{}]", list(&mut self.unresolved.iter())).unwrap();
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn analyzer(program: &[u8]) -> FlowAnalyzer<impl Fn(u16) -> u8 + '_> {
+        let mut analyzer = FlowAnalyzer::new(move |addr: u16| {
+            program.get((addr as usize).wrapping_sub(0x0600)).copied().unwrap_or(0)
+        });
+        analyzer.add_entry(0x0600);
+        analyzer
+    }
+
+    #[test]
+    fn test_blocks_and_functions() {
+        // JSR $0604; BRK; LDX #$03; DEX; BNE $0606; RTS
+        let program = [0x20, 0x04, 0x06, 0x00, 0xa2, 0x03, 0xca, 0xd0, 0xfd, 0x60];
+        let cfg = analyzer(&program).analyze();
+        assert_eq!(cfg.blocks.keys().copied().collect::<Vec<_>>(), vec![0x0600, 0x0604, 0x0606, 0x0609]);
+        assert_eq!(cfg.blocks[&0x0600].calls, vec![0x0604]);
+        assert_eq!(cfg.blocks[&0x0604].successors, vec![0x0606]);
+        assert_eq!(cfg.blocks[&0x0606].successors, vec![0x0606, 0x0609]);
+        assert_eq!(cfg.functions.keys().copied().collect::<Vec<_>>(), vec![0x0600, 0x0604]);
+        assert_eq!(cfg.functions[&0x0604].blocks.len(), 3);
+    }
+
+    #[test]
+    fn test_indirect_jump_and_cdl() {
+        // JMP ($0010); LDA #$01; BRK; NOP; BRK
+        let program = [0x6c, 0x10, 0x00, 0xa9, 0x01, 0x00, 0xea, 0x00];
+        let mut unresolved = analyzer(&program);
+        assert_eq!(unresolved.analyze().unresolved, BTreeSet::from([0x0600]));
+
+        let mut indirect = IndirectJumps::new();
+        indirect.insert(0x0600, 0x0603);
+        unresolved.set_indirect_jumps(indirect);
+        unresolved.set_cdl(vec![0x01; 8], program.len(), &[0x0600]);
+        let cfg = unresolved.analyze();
+        assert!(cfg.unresolved.is_empty());
+        assert_eq!(cfg.blocks[&0x0600].successors, vec![0x0603]);
+        assert!(cfg.functions.contains_key(&0x0606));
+    }
+
+    #[test]
+    fn test_observed_indirect_jump() {
+        // LDA #$0c; STA $10; LDA #$06; STA $11; JMP ($0010); BRK; LDX #$01; BRK
+        let program = vec![0xa9, 0x0c, 0x85, 0x10, 0xa9, 0x06, 0x85, 0x11, 0x6c, 0x10, 0x00, 0x00, 0xa2, 0x01, 0x00];
+        let mut cpu = CPU::new();
+        cpu.load(program).unwrap();
+        cpu.reset();
+        let indirect = IndirectJumps::observe(&mut cpu, 1000);
+        assert_eq!(cpu.rx, 0x01);
+
+        let mut analyzer = FlowAnalyzer::new(|addr| cpu.mem_peek(addr));
+        analyzer.add_vectors();
+        analyzer.set_indirect_jumps(indirect);
+        let cfg = analyzer.analyze();
+        assert!(cfg.unresolved.is_empty());
+        // only the reset vector was set
+        assert_eq!(cfg.functions.keys().copied().collect::<Vec<_>>(), vec![0x0600]);
+        assert_eq!(cfg.blocks[&0x0600].successors, vec![0x060c]);
+    }
+
+    #[test]
+    fn test_snake_call_graph() {
+        let cfg = analyzer(crate::easy6502::SNAKE).analyze();
+        // main, init, the game loop and the ten routines it calls
+        assert_eq!(cfg.functions.len(), 13);
+        // main falls straight through into init
+        assert_eq!(cfg.functions[&0x0600].blocks, BTreeSet::from([0x0600, 0x0606]));
+        assert_eq!(cfg.blocks[&0x0638].calls, vec![0x064d, 0x068d, 0x06c3, 0x0719, 0x0720, 0x072d]);
+        assert!(cfg.unresolved.is_empty());
+        assert!(cfg.to_dot(None).contains("b0638 -> b064d [style=dashed];"));
+    }
+
+    #[test]
+    fn test_json_export() {
+        let cfg = analyzer(&[0xa9, 0x22, 0x00]).analyze();
+        let mut symbols = SymbolTable::new();
+        symbols.insert("start", 0x0600, None);
+        assert_eq!(
+            cfg.to_json(Some(&symbols)),
+            concat!(
+                "{\n",
+                "  \"blocks\": [\n",
+                "    {\"start\": 1536, \"instructions\": [\"0600  LDA #$22\", \"0602  BRK\"], \"successors\": [], \"calls\": []}\n",
+                "  ],\n",
+                "  \"functions\": [\n",
+                "    {\"entry\": 1536, \"name\": \"start\", \"blocks\": [1536]}\n",
+                "  ],\n",
+                "  \"unresolved\": []\n",
+                "}\n",
+            )
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Packed RGB24 pixels plus a record of which rows changed since they
+/// were last presented, so a frontend only uploads what it has to.
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+    dirty: Option<Range<usize>>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![0; width * height * 3],
+            dirty: None,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Bytes per row.
+    pub fn pitch(&self) -> usize {
+        self.width * 3
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, rgb: [u8; 3]) {
+        let offset = (y * self.width + x) * 3;
+        let pixel = &mut self.pixels[offset..offset + 3];
+        if pixel != rgb {
+            pixel.copy_from_slice(&rgb);
+            self.mark_dirty(y..y + 1);
+        }
+    }
+
+    pub fn mark_dirty(&mut self, rows: Range<usize>) {
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(rows.start)..dirty.end.max(rows.end),
+            None => rows,
+        });
+    }
+
+    /// The span of rows changed since the last call, if any.
+    pub fn take_dirty_rows(&mut self) -> Option<Range<usize>> {
+        self.dirty.take()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dirty_rows() {
+        let mut fb = Framebuffer::new(32, 32);
+        assert_eq!(fb.take_dirty_rows(), None);
+        fb.set_pixel(0, 3, [0, 0, 0]);
+        assert_eq!(fb.take_dirty_rows(), None, "unchanged pixels stay clean");
+        fb.set_pixel(5, 3, [0xff, 0, 0]);
+        fb.set_pixel(31, 9, [0, 0xff, 0]);
+        assert_eq!(fb.take_dirty_rows(), Some(3..10));
+        assert_eq!(fb.take_dirty_rows(), None);
+        assert_eq!(fb.pixels()[9 * fb.pitch() + 31 * 3 + 1], 0xff);
+    }
+}
@@ -0,0 +1,213 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::framebuffer::Framebuffer;
+
+/// FNV-1a over the frame's pixels: cheap, stable across platforms and
+/// good enough to spot a changed frame.
+pub fn frame_hash(fb: &Framebuffer) -> u64 {
+    fb.pixels().iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Encodes a binary (P6) PPM.
+pub fn encode_ppm(fb: &Framebuffer) -> Vec<u8> {
+    let mut out = format!("P6\n{} {}\n255\n", fb.width(), fb.height()).into_bytes();
+    out.extend_from_slice(fb.pixels());
+    out
+}
+
+/// Encodes an uncompressed RGB PNG, using stored deflate blocks so no
+/// compression library is needed.
+pub fn encode_png(fb: &Framebuffer) -> Vec<u8> {
+    // every scanline starts with filter type 0
+    let mut raw = Vec::with_capacity((fb.pitch() + 1) * fb.height());
+    for row in fb.pixels().chunks(fb.pitch()) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[0x01, 0x00, 0x00, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        zlib.push(blocks.peek().is_none() as u8);
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(fb.width() as u32).to_be_bytes());
+    header.extend_from_slice(&(fb.height() as u32).to_be_bytes());
+    // 8 bits per channel, truecolor, deflate, no filter, no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    push_chunk(&mut out, b"IHDR", &header);
+    push_chunk(&mut out, b"IDAT", &zlib);
+    push_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+fn push_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(kind.iter().chain(data));
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ScriptError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEvent {
+    Down(u8),
+    Up,
+}
+
+/// Key presses to replay at given frames, one `frame key` per line: a
+/// quoted character (`'w'`), a code (`$77`, `119`) or `up` to release.
+/// On easy6502 the code is what the program reads at $FF. On the NES it
+/// is a mask of the first controller's buttons to press: A $01, B $02,
+/// Select $04, Start $08, Up $10, Down $20, Left $40 and Right $80, and
+/// `up` releases them all. `#` at the start of a line or after a space
+/// starts a comment, so `'#'` is still a key.
+#[derive(Debug, Default)]
+pub struct InputScript {
+    events: Vec<(u64, KeyEvent)>,
+}
+
+impl InputScript {
+    pub fn parse(text: &str) -> Result<InputScript, ScriptError> {
+        let mut events = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: String| ScriptError { line: i + 1, message };
+            let (frame, key) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| error(format!("expected `frame key`, got {:?}", line)))?;
+            let frame = frame.parse().map_err(|_| error(format!("bad frame number {:?}", frame)))?;
+            let key = key.trim();
+            let event = if key.eq_ignore_ascii_case("up") {
+                Some(KeyEvent::Up)
+            } else if let Some(c) = key.strip_prefix('\'').and_then(|k| k.strip_suffix('\'')) {
+                (c.len() == 1 && c.is_ascii()).then(|| KeyEvent::Down(c.as_bytes()[0]))
+            } else if let Some(hex) = key.strip_prefix('$') {
+                u8::from_str_radix(hex, 16).ok().map(KeyEvent::Down)
+            } else {
+                key.parse().ok().map(KeyEvent::Down)
+            };
+            events.push((frame, event.ok_or_else(|| error(format!("bad key {:?}", key)))?));
+        }
+        events.sort_by_key(|&(frame, _)| frame);
+        Ok(InputScript { events })
+    }
+
+    /// Events due at the start of `frame`, in file order.
+    pub fn events_at(&self, frame: u64) -> impl Iterator<Item = KeyEvent> + '_ {
+        let start = self.events.partition_point(|&(at, _)| at < frame);
+        self.events[start..].iter().take_while(move |&&(at, _)| at == frame).map(|&(_, event)| event)
+    }
+}
+
+/// `line` up to a `#` that starts it or follows whitespace.
+pub(crate) fn strip_comment(line: &str) -> &str {
+    let mut prev = ' ';
+    for (i, c) in line.char_indices() {
+        if c == '#' && prev.is_whitespace() {
+            return &line[..i];
+        }
+        prev = c;
+    }
+    line
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn checkerboard() -> Framebuffer {
+        let mut fb = Framebuffer::new(2, 2);
+        fb.set_pixel(0, 0, [0xff, 0x00, 0x00]);
+        fb.set_pixel(1, 1, [0x00, 0x00, 0xff]);
+        fb
+    }
+
+    #[test]
+    fn test_ppm_and_hash() {
+        let fb = checkerboard();
+        let ppm = encode_ppm(&fb);
+        assert_eq!(&ppm[..11], b"P6\n2 2\n255\n");
+        assert_eq!(ppm.len(), 11 + 12);
+        assert_ne!(frame_hash(&fb), frame_hash(&Framebuffer::new(2, 2)));
+        assert_eq!(frame_hash(&fb), frame_hash(&checkerboard()));
+    }
+
+    #[test]
+    fn test_png_checksums() {
+        assert_eq!(crc32(b"IEND".iter()), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        let png = encode_png(&checkerboard());
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[png.len() - 8..], b"IEND\xae\x42\x60\x82");
+    }
+
+    #[test]
+    fn test_input_script() {
+        let script = InputScript::parse("# move, then stop\n10 'w'\n12 $64\n10 up\n20 100\n").unwrap();
+        assert_eq!(script.events_at(10).collect::<Vec<_>>(), vec![KeyEvent::Down(b'w'), KeyEvent::Up]);
+        assert_eq!(script.events_at(12).collect::<Vec<_>>(), vec![KeyEvent::Down(0x64)]);
+        assert_eq!(script.events_at(11).count(), 0);
+        assert_eq!(script.events_at(20).collect::<Vec<_>>(), vec![KeyEvent::Down(100)]);
+        assert_eq!(InputScript::parse("x 'w'\n").unwrap_err().line, 1);
+
+        // a # inside a token is part of it
+        let err = InputScript::parse("5 '#' # hash\n6 $23#not a comment\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        let script = InputScript::parse("5 '#' # hash\n").unwrap();
+        assert_eq!(script.events_at(5).collect::<Vec<_>>(), vec![KeyEvent::Down(b'#')]);
+    }
+}
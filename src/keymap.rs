@@ -0,0 +1,140 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use core::fmt;
+
+use crate::headless::strip_comment;
+use crate::nes::Buttons;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct KeymapError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Maps host keys, by SDL key name (`Left`, `Return`, `A`, `Keypad 8`),
+/// to the code the machine receives: the ASCII code a program sees at $FF
+/// on easy6502, a controller button on the NES. For easy6502, keys that
+/// aren't listed fall back to the character they type, if any.
+pub struct Keymap {
+    keys: BTreeMap<String, u8>,
+    typed: bool,
+}
+
+impl Default for Keymap {
+    /// Enter and Backspace send their control codes; the arrows send
+    /// WASD so that easy6502 games written for those keys just work.
+    fn default() -> Self {
+        let keys = [
+            ("Return", 0x0d),
+            ("Keypad Enter", 0x0d),
+            ("Backspace", 0x08),
+            ("Tab", 0x09),
+            ("Up", b'w'),
+            ("Left", b'a'),
+            ("Down", b's'),
+            ("Right", b'd'),
+        ];
+        Keymap::from_pairs(&keys, true)
+    }
+}
+
+impl Keymap {
+    /// The arrows for the d-pad, Z and X for B and A, Enter for Start and
+    /// right Shift for Select, all on the first controller.
+    pub fn nes() -> Keymap {
+        let keys = [
+            ("Up", Buttons::UP),
+            ("Down", Buttons::DOWN),
+            ("Left", Buttons::LEFT),
+            ("Right", Buttons::RIGHT),
+            ("Z", Buttons::B),
+            ("X", Buttons::A),
+            ("Return", Buttons::START),
+            ("Right Shift", Buttons::SELECT),
+        ];
+        Keymap::from_pairs(&keys.map(|(name, button)| (name, button.bits())), false)
+    }
+
+    fn from_pairs(keys: &[(&str, u8)], typed: bool) -> Keymap {
+        Keymap {
+            keys: keys.iter().map(|&(name, code)| (name.to_ascii_lowercase(), code)).collect(),
+            typed,
+        }
+    }
+
+    /// Applies overrides, one `Key Name = value` per line, where the value
+    /// is a number (`$0d`, `13`) or a quoted character (`'w'`). `#` at the
+    /// start of a line or after a space starts a comment.
+    pub fn parse(&mut self, text: &str) -> Result<(), KeymapError> {
+        for (i, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: String| KeymapError { line: i + 1, message };
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| error(format!("expected `key = value`, got {:?}", line)))?;
+            let value = value.trim();
+            let code = match value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+                Some(c) if c.len() == 1 && c.is_ascii() => Some(c.as_bytes()[0]),
+                Some(_) => None,
+                None => match value.strip_prefix('$') {
+                    Some(hex) => u8::from_str_radix(hex, 16).ok(),
+                    None => value.parse().ok(),
+                },
+            };
+            let code = code.ok_or_else(|| error(format!("bad key code {:?}", value)))?;
+            self.keys.insert(name.trim().to_ascii_lowercase(), code);
+        }
+        Ok(())
+    }
+
+    /// The code for a key press. `typed` is the character the key would
+    /// type, if it is a printable one.
+    pub fn lookup(&self, name: &str, typed: Option<char>) -> Option<u8> {
+        self.keys.get(&name.to_ascii_lowercase()).copied().or_else(|| typed.and_then(|c| self.lookup_text(c)))
+    }
+
+    /// The code for a character typed as text, as the host keyboard layout
+    /// produced it, for keymaps that pass printable characters through.
+    pub fn lookup_text(&self, c: char) -> Option<u8> {
+        Some(c).filter(|c| self.typed && c.is_ascii() && !c.is_ascii_control()).map(|c| c as u8)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_defaults_and_overrides() {
+        let mut keymap = Keymap::default();
+        assert_eq!(keymap.lookup("Return", None), Some(0x0d));
+        assert_eq!(keymap.lookup("Left", None), Some(b'a'));
+        assert_eq!(keymap.lookup("Q", Some('Q')), Some(b'Q'));
+        assert_eq!(keymap.lookup("F1", None), None);
+        assert_eq!(keymap.lookup_text('!'), Some(b'!'));
+        assert_eq!(keymap.lookup_text('é'), None);
+
+        keymap.parse("# arrows as cursor keys\nleft = $11\nF1 = 'h'\nF2 = '#' # hash\n").unwrap();
+        assert_eq!(keymap.lookup("Left", None), Some(0x11));
+        assert_eq!(keymap.lookup("F1", None), Some(b'h'));
+        assert_eq!(keymap.lookup("F2", None), Some(b'#'));
+
+        let err = keymap.parse("Up = 'ww'\n").unwrap_err();
+        assert_eq!(err.line, 1);
+
+        let nes = Keymap::nes();
+        assert_eq!(nes.lookup("x", Some('x')), Some(Buttons::A.bits()));
+        assert_eq!(nes.lookup("Q", Some('q')), None);
+        assert_eq!(nes.lookup_text('q'), None);
+    }
+}
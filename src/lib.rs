@@ -0,0 +1,54 @@
+//! A 6502 emulator core with the machines built on it.
+//!
+//! [`CPU`] executes instructions against a [`Bus`]: 64K of RAM, or a
+//! console's memory map, onto which devices can be mapped. The modules
+//! around it load and assemble programs, disassemble and trace, analyse
+//! control flow and check programs for common mistakes; [`easy6502`] is a
+//! complete machine and [`nes`] the start of another.
+//!
+//! ```
+//! use sens::{StopReason, CPU};
+//!
+//! let mut cpu = CPU::new();
+//! cpu.load(vec![0xa9, 0x42, 0xaa, 0x00]).unwrap(); // LDA #$42; TAX; BRK
+//! cpu.reset();
+//! assert_eq!(cpu.run(), StopReason::Break);
+//! assert_eq!(cpu.rx, 0x42);
+//! println!("{}", sens::disasm::disassemble(|addr| cpu.mem_peek(addr), 0x0600, None).0);
+//! ```
+//!
+//! Without the default `std` feature the core is `no_std` and only needs
+//! `alloc`; randomness and timing come in through [`random::RandomSource`]
+//! and [`scheduler::Timer`].
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(not(feature = "alloc"))]
+compile_error!("sens needs at least the `alloc` feature");
+
+extern crate alloc;
+
+pub mod asm;
+pub mod bus;
+pub mod cartridge;
+mod cpu;
+pub mod disasm;
+pub mod easy6502;
+pub mod flow;
+pub mod framebuffer;
+pub mod headless;
+pub mod keymap;
+pub mod loader;
+pub mod nes;
+#[cfg(feature = "std")]
+pub mod ntsc;
+pub mod opcodes;
+pub mod palette;
+pub mod ppu;
+pub mod random;
+pub mod sanitizer;
+pub mod scheduler;
+pub mod symbols;
+
+pub use bus::Bus;
+pub use cpu::{AddressingMode, ProcessorStatus, StopReason, CPU, NTSC_FRAME_CYCLES};
@@ -0,0 +1,326 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Raw,
+    IntelHex,
+    SRecord,
+    /// C64 program file: a little-endian load address followed by the data.
+    Prg,
+}
+
+impl Format {
+    #[cfg(feature = "std")]
+    pub fn from_path(path: &Path) -> Format {
+        let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        match ext.to_ascii_lowercase().as_str() {
+            "hex" | "ihx" | "ihex" => Format::IntelHex,
+            "srec" | "s19" | "s28" | "s37" | "mot" => Format::SRecord,
+            "prg" => Format::Prg,
+            _ => Format::Raw,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LoadError {
+    /// A segment runs past $FFFF.
+    OutOfBounds { addr: u32, len: usize },
+    Overlap { first: u16, second: u16 },
+    /// The file's start address is above $FFFF.
+    EntryOutOfBounds(u32),
+    Parse { line: usize, message: String },
+    Checksum { line: usize },
+    Empty,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::OutOfBounds { addr, len } => {
+                write!(f, "{} bytes at ${:04x} run past the end of memory", len, addr)
+            }
+            LoadError::Overlap { first, second } => {
+                write!(f, "segment at ${:04x} overlaps segment at ${:04x}", second, first)
+            }
+            LoadError::EntryOutOfBounds(addr) => write!(f, "start address ${:x} is outside memory", addr),
+            LoadError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            LoadError::Checksum { line } => write!(f, "line {}: checksum mismatch", line),
+            LoadError::Empty => write!(f, "no data to load"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub addr: u16,
+    pub data: Vec<u8>,
+}
+
+/// A program ready to be copied into memory: one or more segments and,
+/// when the file format carries one, a start address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub segments: Vec<Segment>,
+    pub entry: Option<u16>,
+}
+
+impl Image {
+    /// `load_addr` only applies to raw binaries; the other formats carry
+    /// their own addresses.
+    pub fn parse(format: Format, bytes: &[u8], load_addr: u16) -> Result<Image, LoadError> {
+        let image = match format {
+            Format::Raw => Image::raw(bytes, load_addr),
+            Format::Prg => {
+                if bytes.len() < 2 {
+                    return Err(LoadError::Empty);
+                }
+                Image::raw(&bytes[2..], u16::from_le_bytes([bytes[0], bytes[1]]))
+            }
+            Format::IntelHex => parse_intel_hex(&String::from_utf8_lossy(bytes))?,
+            Format::SRecord => parse_srecord(&String::from_utf8_lossy(bytes))?,
+        };
+        image.validate()?;
+        Ok(image)
+    }
+
+    pub fn raw(data: &[u8], addr: u16) -> Image {
+        Image {
+            segments: vec![Segment { addr, data: data.to_vec() }],
+            entry: None,
+        }
+    }
+
+    /// Checks that every segment fits below $10000 and that none overlap.
+    pub fn validate(&self) -> Result<(), LoadError> {
+        if self.segments.iter().all(|segment| segment.data.is_empty()) {
+            return Err(LoadError::Empty);
+        }
+        for segment in &self.segments {
+            if segment.addr as usize + segment.data.len() > 0x10000 {
+                return Err(LoadError::OutOfBounds { addr: segment.addr as u32, len: segment.data.len() });
+            }
+        }
+        let mut ranges: Vec<(u16, usize)> = self
+            .segments
+            .iter()
+            .filter(|segment| !segment.data.is_empty())
+            .map(|segment| (segment.addr, segment.addr as usize + segment.data.len()))
+            .collect();
+        ranges.sort();
+        for pair in ranges.windows(2) {
+            if (pair[1].0 as usize) < pair[0].1 {
+                return Err(LoadError::Overlap { first: pair[0].0, second: pair[1].0 });
+            }
+        }
+        Ok(())
+    }
+
+    /// Where execution should start: an explicit start record, else the
+    /// first segment.
+    pub fn entry(&self) -> u16 {
+        self.entry.unwrap_or_else(|| self.segments.first().map_or(0, |segment| segment.addr))
+    }
+
+    fn push(&mut self, addr: u32, data: &[u8]) -> Result<(), LoadError> {
+        if addr as usize + data.len() > 0x10000 {
+            return Err(LoadError::OutOfBounds { addr, len: data.len() });
+        }
+        match self.segments.last_mut() {
+            // records are normally contiguous, so merge them as they come
+            Some(last) if last.addr as usize + last.data.len() == addr as usize => {
+                last.data.extend_from_slice(data);
+            }
+            _ => self.segments.push(Segment { addr: addr as u16, data: data.to_vec() }),
+        }
+        Ok(())
+    }
+}
+
+fn parse_error(line: usize, message: &str) -> LoadError {
+    LoadError::Parse { line, message: message.to_string() }
+}
+
+fn hex_bytes(text: &str, line: usize) -> Result<Vec<u8>, LoadError> {
+    if !text.len().is_multiple_of(2) {
+        return Err(parse_error(line, "odd number of hex digits"));
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| parse_error(line, "invalid hex digit"))
+        })
+        .collect()
+}
+
+fn parse_intel_hex(text: &str) -> Result<Image, LoadError> {
+    let mut image = Image { segments: Vec::new(), entry: None };
+    let mut base: u32 = 0;
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let record = line
+            .strip_prefix(':')
+            .ok_or_else(|| parse_error(line_no, "record does not start with ':'"))?;
+        let bytes = hex_bytes(record, line_no)?;
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(parse_error(line_no, "record length does not match byte count"));
+        }
+        if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+            return Err(LoadError::Checksum { line: line_no });
+        }
+        let offset = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+        let data = &bytes[4..bytes.len() - 1];
+        match bytes[3] {
+            0x00 => image.push(base + offset, data)?,
+            0x01 => break,
+            kind @ (0x02 | 0x04) if data.len() != 2 => {
+                return Err(parse_error(line_no, &format!("record type {:02x} needs 2 data bytes", kind)));
+            }
+            kind @ (0x03 | 0x05) if data.len() != 4 => {
+                return Err(parse_error(line_no, &format!("record type {:02x} needs 4 data bytes", kind)));
+            }
+            0x02 => base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 4,
+            0x04 => base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 16,
+            // start segment address (CS:IP) and start linear address
+            0x03 => {
+                let cs = u16::from_be_bytes([data[0], data[1]]) as u32;
+                let ip = u16::from_be_bytes([data[2], data[3]]) as u32;
+                image.entry = Some(entry_address((cs << 4) + ip)?);
+            }
+            0x05 => image.entry = Some(entry_address(u32::from_be_bytes([data[0], data[1], data[2], data[3]]))?),
+            kind => return Err(parse_error(line_no, &format!("unsupported record type {:02x}", kind))),
+        }
+    }
+    Ok(image)
+}
+
+fn entry_address(addr: u32) -> Result<u16, LoadError> {
+    u16::try_from(addr).map_err(|_| LoadError::EntryOutOfBounds(addr))
+}
+
+fn parse_srecord(text: &str) -> Result<Image, LoadError> {
+    let mut image = Image { segments: Vec::new(), entry: None };
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let kind = line
+            .strip_prefix('S')
+            .and_then(|rest| rest.chars().next())
+            .ok_or_else(|| parse_error(line_no, "record does not start with 'S'"))?;
+        let record = line.get(2..).ok_or_else(|| parse_error(line_no, "record too short"))?;
+        let bytes = hex_bytes(record, line_no)?;
+        if bytes.is_empty() || bytes.len() != bytes[0] as usize + 1 {
+            return Err(parse_error(line_no, "record length does not match byte count"));
+        }
+        let sum = bytes[..bytes.len() - 1].iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        if !sum != bytes[bytes.len() - 1] {
+            return Err(LoadError::Checksum { line: line_no });
+        }
+        let addr_len = match kind {
+            '0' | '1' | '5' | '9' => 2,
+            '2' | '6' | '8' => 3,
+            '3' | '7' => 4,
+            _ => return Err(parse_error(line_no, &format!("unsupported record type S{}", kind))),
+        };
+        if bytes.len() < addr_len + 2 {
+            return Err(parse_error(line_no, "record too short"));
+        }
+        let addr = bytes[1..=addr_len].iter().fold(0u32, |addr, b| addr << 8 | *b as u32);
+        let data = &bytes[addr_len + 1..bytes.len() - 1];
+        match kind {
+            '1' | '2' | '3' => image.push(addr, data)?,
+            '7' | '8' | '9' => image.entry = Some(entry_address(addr)?),
+            // header and record counts
+            _ => {}
+        }
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prg_and_raw() {
+        let image = Image::parse(Format::Prg, &[0x01, 0x08, 0xa9, 0x00], 0).unwrap();
+        assert_eq!(image.segments, vec![Segment { addr: 0x0801, data: vec![0xa9, 0x00] }]);
+        assert_eq!(image.entry(), 0x0801);
+        assert_eq!(
+            Image::parse(Format::Raw, &[0; 0x20], 0xfff0),
+            Err(LoadError::OutOfBounds { addr: 0xfff0, len: 0x20 })
+        );
+    }
+
+    #[test]
+    fn test_intel_hex() {
+        let text = ":03060000A901004D\n:02FFFC000006FD\n:0400000500000600F1\n:00000001FF\n";
+        let image = Image::parse(Format::IntelHex, text.as_bytes(), 0).unwrap();
+        assert_eq!(image.segments.len(), 2);
+        assert_eq!(image.segments[0], Segment { addr: 0x0600, data: vec![0xa9, 0x01, 0x00] });
+        assert_eq!(image.entry, Some(0x0600));
+        let corrupt = text.replace("4D", "4E");
+        assert_eq!(
+            Image::parse(Format::IntelHex, corrupt.as_bytes(), 0),
+            Err(LoadError::Checksum { line: 1 })
+        );
+    }
+
+    /// An Intel HEX record with its byte count and checksum filled in.
+    fn hex_record(kind: u8, offset: u16, data: &[u8]) -> String {
+        let mut bytes = vec![data.len() as u8, (offset >> 8) as u8, offset as u8, kind];
+        bytes.extend_from_slice(data);
+        bytes.push(bytes.iter().fold(0u8, |sum, b| sum.wrapping_sub(*b)));
+        bytes.iter().fold(String::from(":"), |line, b| line + &format!("{:02X}", b)) + "\n"
+    }
+
+    #[test]
+    fn test_intel_hex_start_addresses() {
+        let data = hex_record(0x00, 0x0600, &[0xea]);
+        let parse = |start: String| Image::parse(Format::IntelHex, (data.clone() + &start).as_bytes(), 0);
+        // CS:IP $1000:$0123 is $10123
+        assert_eq!(parse(hex_record(0x03, 0, &[0x10, 0x00, 0x01, 0x23])), Err(LoadError::EntryOutOfBounds(0x10123)));
+        assert_eq!(parse(hex_record(0x05, 0, &[0x00, 0x01, 0x06, 0x00])), Err(LoadError::EntryOutOfBounds(0x10600)));
+        assert_eq!(parse(hex_record(0x03, 0, &[0x00, 0x60, 0x00, 0x10])).unwrap().entry, Some(0x0610));
+
+        let err = parse(hex_record(0x05, 0, &[0x06, 0x00])).unwrap_err();
+        assert_eq!(err.to_string(), "line 2: record type 05 needs 4 data bytes");
+        let err = parse(hex_record(0x04, 0, &[0x00])).unwrap_err();
+        assert_eq!(err.to_string(), "line 2: record type 04 needs 2 data bytes");
+    }
+
+    #[test]
+    fn test_srecord() {
+        let text = "S00600004844521B\nS1060600A9010049\nS1050603E80009\nS9030600F6\n";
+        let image = Image::parse(Format::SRecord, text.as_bytes(), 0).unwrap();
+        assert_eq!(image.segments, vec![Segment { addr: 0x0600, data: vec![0xa9, 0x01, 0x00, 0xe8, 0x00] }]);
+        assert_eq!(image.entry, Some(0x0600));
+    }
+
+    #[test]
+    fn test_overlapping_segments() {
+        let image = Image {
+            segments: vec![
+                Segment { addr: 0x0600, data: vec![0; 4] },
+                Segment { addr: 0x0602, data: vec![0; 4] },
+            ],
+            entry: None,
+        };
+        assert_eq!(image.validate(), Err(LoadError::Overlap { first: 0x0600, second: 0x0602 }));
+    }
+}
@@ -0,0 +1,556 @@
+//! The `sens` command-line frontend: loads a program into a machine and
+//! runs it in a window, headless, or through the analysis tools.
+
+#[cfg(feature = "sdl")]
+mod sdl;
+
+/// Stands in for the SDL frontend when the crate is built without it.
+#[cfg(not(feature = "sdl"))]
+mod sdl {
+    use sens::keymap::Keymap;
+    use sens::scheduler::Scheduler;
+    use sens::symbols::SymbolTable;
+    use sens::{StopReason, CPU};
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn run_window(
+        _cpu: &mut CPU,
+        _machine: &dyn super::Machine,
+        _title: &str,
+        _scale: u32,
+        _keymap: &Keymap,
+        _key_up: bool,
+        _scheduler: Scheduler,
+        _trace: bool,
+        _symbols: Option<&SymbolTable>,
+        _reported: &mut usize,
+    ) -> StopReason {
+        eprintln!("built without the sdl feature; only --headless runs are available");
+        std::process::exit(1);
+    }
+}
+
+use std::cell::RefMut;
+use std::io::Write;
+use std::path::Path;
+
+use sens::asm;
+use sens::cartridge::Cartridge;
+use sens::disasm;
+use sens::easy6502::{self, Easy6502};
+use sens::flow::{FlowAnalyzer, IndirectJumps};
+use sens::framebuffer::Framebuffer;
+use sens::headless::{self, InputScript, KeyEvent};
+use sens::keymap::Keymap;
+use sens::loader::{Format, Image};
+use sens::nes::{Buttons, Nes};
+use sens::ntsc::{NtscFilter, Preset};
+use sens::palette::{NtscSettings, Palette};
+use sens::ppu::Renderer;
+use sens::random::{RandomSource, Scripted, Xorshift};
+use sens::sanitizer::{Sanitizer, SanitizerConfig};
+use sens::scheduler::{Clock, Scheduler};
+use sens::symbols::SymbolTable;
+use sens::{StopReason, CPU};
+
+/// What the frontends need from a machine: a screen to show and
+/// somewhere to send key presses, as mapped by the keymap.
+pub trait Machine {
+    fn screen(&self) -> RefMut<'_, Framebuffer>;
+    fn key_down(&self, code: u8);
+    fn key_up(&self, code: u8);
+    fn release_keys(&self);
+}
+
+impl Machine for Easy6502 {
+    fn screen(&self) -> RefMut<'_, Framebuffer> {
+        Easy6502::screen(self)
+    }
+
+    fn key_down(&self, code: u8) {
+        Easy6502::key_down(self, code)
+    }
+
+    fn key_up(&self, _code: u8) {
+        Easy6502::key_up(self)
+    }
+
+    fn release_keys(&self) {
+        Easy6502::key_up(self)
+    }
+}
+
+/// Key codes are the first controller's buttons.
+impl Machine for Nes {
+    fn screen(&self) -> RefMut<'_, Framebuffer> {
+        Nes::screen(self)
+    }
+
+    fn key_down(&self, code: u8) {
+        self.controllers().press(0, Buttons::from_bits_truncate(code));
+    }
+
+    fn key_up(&self, code: u8) {
+        self.controllers().release(0, Buttons::from_bits_truncate(code));
+    }
+
+    fn release_keys(&self) {
+        self.controllers().release(0, Buttons::all());
+    }
+}
+
+fn report_findings(cpu: &CPU, reported: usize, symbols: Option<&SymbolTable>) -> usize {
+    let Some(sanitizer) = cpu.sanitizer() else {
+        return 0;
+    };
+    for finding in &sanitizer.findings()[reported..] {
+        eprint!("{}", finding.display(symbols));
+    }
+    sanitizer.findings().len()
+}
+
+
+fn parse_count(text: &str) -> u64 {
+    text.parse().unwrap_or_else(|_| {
+        eprintln!("invalid count {}", text);
+        std::process::exit(1);
+    })
+}
+
+/// `$0600`, `0x0600` or plain decimal.
+fn parse_address(text: &str) -> u16 {
+    read_address(text).unwrap_or_else(|| {
+        eprintln!("invalid address {}", text);
+        std::process::exit(1);
+    })
+}
+
+fn read_address(text: &str) -> Option<u16> {
+    match text.strip_prefix('$').or_else(|| text.strip_prefix("0x")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Loads `path` into `cpu`, assembling `.asm` sources and picking the
+/// binary format from the extension otherwise. Returns where the image
+/// starts.
+fn load_program(cpu: &mut CPU, path: &Path, load_addr: u16, entry: Option<u16>) -> Result<u16, String> {
+    let bytes = std::fs::read(path).map_err(|err| err.to_string())?;
+    let image = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("asm" | "s") => asm::assemble(&String::from_utf8_lossy(&bytes), load_addr).map_err(|err| err.to_string())?,
+        _ => Image::parse(Format::from_path(path), &bytes, load_addr).map_err(|err| err.to_string())?,
+    };
+    cpu.load_image(&image, entry).map_err(|err| err.to_string())?;
+    Ok(image.segments.iter().map(|segment| segment.addr).min().unwrap_or(load_addr))
+}
+
+/// How long a headless run lasts.
+enum Limit {
+    Frames(u64),
+    Cycles(u64),
+}
+
+/// Runs `cycles` worth of instructions, reporting sanitizer findings and
+/// tracing as it goes.
+fn emulate(cpu: &mut CPU, cycles: u64, trace: bool, symbols: Option<&SymbolTable>, reported: &mut usize) -> StopReason {
+    let target = cpu.cycles() + cycles;
+    cpu.run_while(|cpu| {
+        *reported = report_findings(cpu, *reported, symbols);
+        if trace {
+            eprintln!("{}", disasm::trace(cpu, symbols));
+        }
+        (cpu.cycles() >= target).then_some(StopReason::BudgetExhausted)
+    })
+}
+
+/// Runs without a window as fast as possible, printing a hash of every
+/// frame to stdout and optionally saving the last one.
+#[allow(clippy::too_many_arguments)]
+fn run_headless(
+    cpu: &mut CPU,
+    machine: &dyn Machine,
+    clock: Clock,
+    limit: Limit,
+    script: &InputScript,
+    dump: Option<&Path>,
+    trace: bool,
+    symbols: Option<&SymbolTable>,
+    reported: &mut usize,
+) -> StopReason {
+    let mut scheduler = Scheduler::unthrottled(clock);
+    let start = cpu.cycles();
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    let mut frame = 0;
+    let reason = loop {
+        let budget = match limit {
+            Limit::Frames(frames) if frame >= frames => break StopReason::BudgetExhausted,
+            Limit::Cycles(cycles) if cpu.cycles() - start >= cycles => break StopReason::BudgetExhausted,
+            Limit::Cycles(cycles) => scheduler.frame_budget().min(cycles - (cpu.cycles() - start)),
+            Limit::Frames(_) => scheduler.frame_budget(),
+        };
+        for event in script.events_at(frame) {
+            match event {
+                KeyEvent::Down(code) => machine.key_down(code),
+                KeyEvent::Up => machine.release_keys(),
+            }
+        }
+        let reason = emulate(cpu, budget, trace, symbols, reported);
+        writeln!(out, "frame {} {:016x}", frame, headless::frame_hash(&machine.screen())).expect("stdout");
+        frame += 1;
+        if reason != StopReason::BudgetExhausted {
+            break reason;
+        }
+    };
+    out.flush().expect("stdout");
+
+    if let Some(path) = dump {
+        let screen = machine.screen();
+        let encoded = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("png") => headless::encode_png(&screen),
+            _ => headless::encode_ppm(&screen),
+        };
+        if let Err(err) = std::fs::write(path, encoded) {
+            eprintln!("{}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+    }
+    reason
+}
+
+fn main() {
+    //load the game
+    let mut cpu = CPU::new();
+    let mut machine = None;
+    let mut scale = None;
+    let mut seed = None;
+    let mut script = None;
+    let mut keymap_path = None;
+    let mut key_up = false;
+    let mut clock = None;
+    let mut renderer = Renderer::default();
+    let mut palette_source = None;
+    let mut ntsc: Option<NtscSettings> = None;
+    let mut filter = None;
+    let mut throttle = true;
+    let mut speed = 1.0;
+    let mut headless = false;
+    let mut limit = None;
+    let mut input = InputScript::default();
+    let mut dump = None;
+    let mut sanitize = None;
+    let mut symbols = SymbolTable::new();
+    let mut trace = false;
+    let mut export_cfg = None;
+    let mut cdl = None;
+    let mut breaks = Vec::new();
+    let mut program = None;
+    let mut load_addr = easy6502::LOAD_ADDR;
+    let mut entry = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--sanitize" => sanitize = Some(SanitizerConfig::default()),
+            "--sanitize=strict" => sanitize = Some(SanitizerConfig::strict()),
+            "--trace" => trace = true,
+            "--key-up" => key_up = true,
+            "--headless" => headless = true,
+            "--cfg=dot" | "--cfg=json" => export_cfg = Some(arg[6..].to_string()),
+            _ => {
+                if let Some(path) = arg.strip_prefix("--symbols=") {
+                    if let Err(err) = symbols.load(Path::new(path)) {
+                        eprintln!("{}: {}", path, err);
+                        std::process::exit(1);
+                    }
+                } else if let Some(path) = arg.strip_prefix("--keymap=") {
+                    keymap_path = Some(path.to_string());
+                } else if let Some(count) = arg.strip_prefix("--frames=") {
+                    limit = Some(Limit::Frames(parse_count(count)));
+                } else if let Some(count) = arg.strip_prefix("--cycles=") {
+                    limit = Some(Limit::Cycles(parse_count(count)));
+                } else if let Some(path) = arg.strip_prefix("--input=") {
+                    let parsed = std::fs::read_to_string(path)
+                        .map_err(|err| err.to_string())
+                        .and_then(|text| InputScript::parse(&text).map_err(|err| err.to_string()));
+                    match parsed {
+                        Ok(parsed) => input = parsed,
+                        Err(err) => {
+                            eprintln!("{}: {}", path, err);
+                            std::process::exit(1);
+                        }
+                    }
+                } else if let Some(path) = arg.strip_prefix("--dump=") {
+                    dump = Some(path.to_string());
+                } else if let Some(name) = arg.strip_prefix("--clock=") {
+                    match Clock::from_name(name) {
+                        Some(named) => clock = Some(named),
+                        None if name == "unthrottled" => throttle = false,
+                        None => {
+                            eprintln!("unknown clock {}", name);
+                            std::process::exit(1);
+                        }
+                    }
+                } else if let Some(factor) = arg.strip_prefix("--speed=") {
+                    speed = match factor.parse::<f64>() {
+                        Ok(factor) if factor > 0.0 => factor,
+                        _ => {
+                            eprintln!("invalid speed {}", factor);
+                            std::process::exit(1);
+                        }
+                    };
+                } else if let Some(name) = arg.strip_prefix("--ppu=") {
+                    match Renderer::from_name(name) {
+                        Some(named) => renderer = named,
+                        None => {
+                            eprintln!("unknown ppu renderer {}", name);
+                            std::process::exit(1);
+                        }
+                    }
+                } else if let Some(name) = arg.strip_prefix("--ntsc=") {
+                    match Preset::from_name(name) {
+                        Some(preset) => filter = Some(preset),
+                        None => {
+                            eprintln!("unknown ntsc preset {}", name);
+                            std::process::exit(1);
+                        }
+                    }
+                } else if let Some(source) = arg.strip_prefix("--palette=") {
+                    palette_source = Some(source.to_string());
+                } else if let Some((name, value)) = arg.strip_prefix("--").and_then(|arg| arg.split_once('='))
+                    .filter(|(name, _)| ["hue", "saturation", "contrast", "brightness"].contains(name))
+                {
+                    let Ok(value) = value.parse::<f64>() else {
+                        eprintln!("invalid {} {}", name, value);
+                        std::process::exit(1);
+                    };
+                    let settings = ntsc.get_or_insert_with(NtscSettings::default);
+                    match name {
+                        "hue" => settings.hue = value,
+                        "saturation" => settings.saturation = value,
+                        "contrast" => settings.contrast = value,
+                        _ => settings.brightness = value,
+                    }
+                } else if let Some(name) = arg.strip_prefix("--machine=") {
+                    machine = Some(name.to_string());
+                } else if let Some(factor) = arg.strip_prefix("--scale=") {
+                    scale = match factor.parse::<u32>() {
+                        Ok(factor) if factor > 0 => Some(factor),
+                        _ => {
+                            eprintln!("invalid scale {}", factor);
+                            std::process::exit(1);
+                        }
+                    };
+                } else if let Some(value) = arg.strip_prefix("--seed=") {
+                    match value.parse::<u64>() {
+                        Ok(value) => seed = Some(value),
+                        Err(_) => {
+                            eprintln!("invalid seed {}", value);
+                            std::process::exit(1);
+                        }
+                    }
+                } else if let Some(values) = arg.strip_prefix("--random=") {
+                    let values = values.split(',').map(|value| u8::try_from(parse_address(value)));
+                    match values.collect::<Result<Vec<u8>, _>>() {
+                        Ok(values) => script = Some(values),
+                        Err(_) => {
+                            eprintln!("--random values must be bytes");
+                            std::process::exit(1);
+                        }
+                    }
+                } else if let Some(addr) = arg.strip_prefix("--load-address=") {
+                    load_addr = parse_address(addr);
+                } else if let Some(addr) = arg.strip_prefix("--entry=") {
+                    entry = Some(parse_address(addr));
+                } else if !arg.starts_with("--") {
+                    if let Some(first) = &program {
+                        eprintln!("unexpected argument {} after program {}", arg, first);
+                        std::process::exit(1);
+                    }
+                    program = Some(arg.clone());
+                } else if let Some(location) = arg.strip_prefix("--break=") {
+                    breaks.push(location.to_string());
+                } else if let Some(path) = arg.strip_prefix("--cdl=") {
+                    match std::fs::read(path) {
+                        Ok(bytes) => cdl = Some(bytes),
+                        Err(err) => {
+                            eprintln!("{}: {}", path, err);
+                            std::process::exit(1);
+                        }
+                    }
+                } else {
+                    eprintln!("unknown option {}", arg);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+    let is_rom = |path: &String| Path::new(path).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("nes"));
+    let nes = match machine.as_deref() {
+        Some("nes") => true,
+        Some("easy6502") => false,
+        Some(name) => {
+            eprintln!("unknown machine {}", name);
+            std::process::exit(1);
+        }
+        None => program.as_ref().is_some_and(is_rom),
+    };
+    // the filter's picture is already twice the size
+    let scale = scale.unwrap_or(if filter.is_some() { 2 } else if nes { 3 } else { 10 });
+    // a controller needs to know when buttons are let go
+    let key_up = key_up || nes;
+    let mut keymap = if nes { Keymap::nes() } else { Keymap::default() };
+    if let Some(path) = keymap_path {
+        let parsed = std::fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| keymap.parse(&text).map_err(|err| err.to_string()));
+        if let Err(err) = parsed {
+            eprintln!("{}: {}", path, err);
+            std::process::exit(1);
+        }
+    }
+    let palette = match palette_source.as_deref() {
+        Some("ntsc") => Some(Palette::generate(ntsc.unwrap_or_default())),
+        Some(path) => {
+            let parsed = std::fs::read(path)
+                .map_err(|err| err.to_string())
+                .and_then(|bytes| Palette::parse(&bytes).map_err(|err| err.to_string()));
+            match parsed {
+                Ok(palette) => Some(palette),
+                Err(err) => {
+                    eprintln!("{}: {}", path, err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        // the generator's settings imply it
+        None => ntsc.map(Palette::generate),
+    };
+    let mut region = None;
+    let mut prg_bases = vec![easy6502::LOAD_ADDR];
+    // only the NES has CHR-ROM after PRG-ROM in its code/data logs
+    let mut prg_len = usize::MAX;
+    let machine: Box<dyn Machine> = if nes {
+        let Some(path) = &program else {
+            eprintln!("the nes machine needs a .nes file to run");
+            std::process::exit(1);
+        };
+        let booted = std::fs::read(path)
+            .map_err(|err| err.to_string())
+            .and_then(|bytes| Cartridge::parse(&bytes).map_err(|err| err.to_string()))
+            .and_then(|cartridge| Nes::attach(&mut cpu, cartridge).map_err(|err| err.to_string()));
+        match booted {
+            Ok(mut nes) => {
+                nes.set_renderer(renderer);
+                let settings = ntsc.unwrap_or_default();
+                // the RGB preset draws in the palette asked for, if any
+                let filter_palette = palette.clone().unwrap_or_else(|| Palette::generate(settings));
+                nes.set_filter(filter.map(|preset| NtscFilter::new(preset, settings, filter_palette)));
+                if let Some(palette) = palette {
+                    nes.set_palette(palette);
+                }
+                region = Some(nes.cartridge().timing.clock());
+                prg_bases = nes.prg_bases();
+                prg_len = nes.cartridge().prg_rom.len();
+                Box::new(nes)
+            }
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        let random: Box<dyn RandomSource> = match script {
+            Some(values) => Box::new(Scripted::new(values)),
+            None => {
+                // logged so that a run can be replayed with --seed
+                let seed = seed.unwrap_or_else(|| {
+                    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+                    now.as_secs() ^ (now.subsec_nanos() as u64) << 32
+                });
+                eprintln!("seed: {}", seed);
+                Box::new(Xorshift::new(seed))
+            }
+        };
+        Box::new(Easy6502::attach(&mut cpu, random))
+    };
+    let clock = clock.or(region).unwrap_or(Clock::EASY6502);
+    if let Some(config) = sanitize {
+        cpu.enable_sanitizer(config);
+    }
+    for location in &breaks {
+        let addr = read_address(location).or_else(|| symbols.resolve(location).map(|symbol| symbol.addr));
+        match addr {
+            Some(addr) => cpu.add_breakpoint(addr),
+            None => {
+                eprintln!("unknown breakpoint location {}", location);
+                std::process::exit(1);
+            }
+        }
+    }
+    let title = match &program {
+        Some(path) if nes => Path::new(path).file_name().map_or(String::new(), |name| name.to_string_lossy().into_owned()),
+        Some(path) => {
+            let path = Path::new(path);
+            match load_program(&mut cpu, path, load_addr, entry) {
+                Ok(base) => prg_bases = vec![base],
+                Err(err) => {
+                    eprintln!("{}: {}", path.display(), err);
+                    std::process::exit(1);
+                }
+            }
+            path.file_name().map_or(String::new(), |name| name.to_string_lossy().into_owned())
+        }
+        None => {
+            cpu.load(easy6502::SNAKE.to_vec()).expect("snake fits in memory");
+            String::from("Snake game")
+        }
+    };
+    cpu.reset();
+
+    if let Some(format) = export_cfg {
+        let symbols = (!symbols.is_empty()).then_some(&symbols);
+        // the graph is of the program as loaded, with the targets of
+        // indirect jumps taken from a bounded run
+        let image: Vec<u8> = (0..=0xffff).map(|addr| cpu.mem_peek(addr)).collect();
+        let cycles = match limit.unwrap_or(Limit::Frames(60)) {
+            Limit::Frames(frames) => (frames as f64 * clock.cycles_per_frame()) as u64,
+            Limit::Cycles(cycles) => cycles,
+        };
+        let entry = cpu.pc;
+        let indirect = IndirectJumps::observe(&mut cpu, cycles);
+        let mut analyzer = FlowAnalyzer::new(|addr| image[addr as usize]);
+        analyzer.add_entry(entry);
+        analyzer.add_vectors();
+        analyzer.set_indirect_jumps(indirect);
+        if let Some(cdl) = cdl {
+            analyzer.set_cdl(cdl, prg_len, &prg_bases);
+        }
+        let graph = analyzer.analyze();
+        match format.as_str() {
+            "dot" => print!("{}", graph.to_dot(symbols)),
+            _ => print!("{}", graph.to_json(symbols)),
+        }
+        return;
+    }
+
+    let mut reported_findings = 0;
+    let symbols = (!symbols.is_empty()).then_some(&symbols);
+
+    let reason = if headless {
+        let limit = limit.unwrap_or(Limit::Frames(60));
+        let dump = dump.as_deref().map(Path::new);
+        run_headless(&mut cpu, machine.as_ref(), clock, limit, &input, dump, trace, symbols, &mut reported_findings)
+    } else {
+        let mut scheduler = if throttle { Scheduler::new(clock) } else { Scheduler::unthrottled(clock) };
+        scheduler.set_speed(speed);
+        sdl::run_window(&mut cpu, machine.as_ref(), &title, scale, &keymap, key_up, scheduler, trace, symbols, &mut reported_findings)
+    };
+
+    report_findings(&cpu, reported_findings, symbols);
+    if let StopReason::Breakpoint(_) | StopReason::Jam(_) = reason {
+        eprintln!("stopped: {:?}", reason);
+        eprintln!("{}", disasm::trace(&cpu, symbols));
+    }
+    if cpu.sanitizer().is_some_and(Sanitizer::has_errors) {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,635 @@
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::{Ref, RefCell, RefMut};
+
+use bitflags::bitflags;
+
+use crate::bus::{Bus, Memory};
+use crate::cartridge::{Cartridge, CartridgeError};
+use crate::framebuffer::Framebuffer;
+#[cfg(feature = "std")]
+use crate::ntsc::{NtscFilter, FILTER_HEIGHT, FILTER_WIDTH};
+use crate::palette::Palette;
+use crate::ppu::{Ppu, Renderer};
+use crate::CPU;
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 240;
+
+/// A chip decoding part of the NES address space, given addresses as the
+/// CPU puts them on the bus. Reads return `None` where the chip leaves the
+/// data bus floating, so the CPU sees open bus.
+pub trait Device {
+    fn read(&mut self, addr: u16) -> Option<u8>;
+    fn write(&mut self, addr: u16, val: u8);
+    /// Like `read`, without side effects.
+    fn peek(&self, addr: u16) -> Option<u8>;
+
+    /// Lets the chip run for `cycles` CPU cycles.
+    fn tick(&mut self, _cycles: u64) {}
+
+    /// Whether the chip pulled NMI, clearing it.
+    fn take_nmi(&mut self) -> bool {
+        false
+    }
+}
+
+/// Lets the machine keep a handle on a chip it has plugged into the bus.
+impl<D: Device> Device for Rc<RefCell<D>> {
+    fn read(&mut self, addr: u16) -> Option<u8> {
+        self.borrow_mut().read(addr)
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.borrow_mut().write(addr, val)
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        self.borrow().peek(addr)
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.borrow_mut().tick(cycles)
+    }
+
+    fn take_nmi(&mut self) -> bool {
+        self.borrow_mut().take_nmi()
+    }
+}
+
+/// An empty slot.
+pub struct Unmapped;
+
+impl Device for Unmapped {
+    fn read(&mut self, _addr: u16) -> Option<u8> {
+        None
+    }
+
+    fn write(&mut self, _addr: u16, _val: u8) {}
+
+    fn peek(&self, _addr: u16) -> Option<u8> {
+        None
+    }
+}
+
+pub const RAM_SIZE: usize = 0x800;
+
+/// The 2A03's memory map:
+///
+/// - `$0000-$1FFF` 2K of internal RAM, mirrored four times
+/// - `$2000-$3FFF` the eight PPU registers, mirrored every 8 bytes
+/// - `$4000-$4017` APU and I/O registers
+/// - `$4018-$401F` test-mode registers, disabled on retail consoles
+/// - `$4020-$FFFF` the cartridge
+///
+/// Every transfer leaves its value on the data bus, and reads of anything
+/// that doesn't drive it return that value.
+pub struct NesBus {
+    ram: Box<[u8; RAM_SIZE]>,
+    ppu: Box<dyn Device>,
+    io: Box<dyn Device>,
+    cartridge: Box<dyn Device>,
+    open_bus: u8,
+    dma: bool,
+}
+
+impl Default for NesBus {
+    fn default() -> Self {
+        NesBus::new()
+    }
+}
+
+impl NesBus {
+    pub fn new() -> NesBus {
+        NesBus {
+            ram: Box::new([0; RAM_SIZE]),
+            ppu: Box::new(Unmapped),
+            io: Box::new(Unmapped),
+            cartridge: Box::new(Unmapped),
+            open_bus: 0,
+            dma: false,
+        }
+    }
+
+    /// Receives `$2000-$2007`, whichever mirror was accessed.
+    pub fn set_ppu<D: Device + 'static>(&mut self, ppu: D) {
+        self.ppu = Box::new(ppu);
+    }
+
+    pub fn set_io<D: Device + 'static>(&mut self, io: D) {
+        self.io = Box::new(io);
+    }
+
+    pub fn set_cartridge<D: Device + 'static>(&mut self, cartridge: D) {
+        self.cartridge = Box::new(cartridge);
+    }
+
+    /// The value last driven onto the data bus.
+    pub fn open_bus(&self) -> u8 {
+        self.open_bus
+    }
+
+    /// Copies CPU page `page` into OAM through $2004. The copy is done at
+    /// once; the CPU accounts for the cycles it loses.
+    fn oam_dma(&mut self, page: u8) {
+        let base = (page as u16) << 8;
+        for offset in 0..=0xff {
+            let val = self.read(base | offset);
+            self.ppu.write(0x2004, val);
+        }
+        self.dma = true;
+    }
+}
+
+impl Memory for NesBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        let val = match addr {
+            0x0000..=0x1fff => Some(self.ram[addr as usize % RAM_SIZE]),
+            0x2000..=0x3fff => self.ppu.read(0x2000 | addr & 7),
+            0x4000..=0x4017 => self.io.read(addr),
+            0x4018..=0x401f => None,
+            0x4020..=0xffff => self.cartridge.read(addr),
+        };
+        self.open_bus = val.unwrap_or(self.open_bus);
+        self.open_bus
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.open_bus = val;
+        match addr {
+            0x0000..=0x1fff => self.ram[addr as usize % RAM_SIZE] = val,
+            0x2000..=0x3fff => self.ppu.write(0x2000 | addr & 7, val),
+            0x4014 => self.oam_dma(val),
+            0x4000..=0x4017 => self.io.write(addr, val),
+            0x4018..=0x401f => {}
+            0x4020..=0xffff => self.cartridge.write(addr, val),
+        }
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        let val = match addr {
+            0x0000..=0x1fff => Some(self.ram[addr as usize % RAM_SIZE]),
+            0x2000..=0x3fff => self.ppu.peek(0x2000 | addr & 7),
+            0x4000..=0x4017 => self.io.peek(addr),
+            0x4018..=0x401f => None,
+            0x4020..=0xffff => self.cartridge.peek(addr),
+        };
+        val.unwrap_or(self.open_bus)
+    }
+
+    /// The registers and PRG-ROM; PRG-RAM at $6000-$7FFF starts out as
+    /// undefined as the internal RAM.
+    fn is_mapped(&self, addr: u16) -> bool {
+        matches!(addr, 0x2000..=0x5fff | 0x8000..=0xffff)
+    }
+
+    fn take_dma(&mut self) -> bool {
+        core::mem::take(&mut self.dma)
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.ppu.tick(cycles);
+    }
+
+    fn take_nmi(&mut self) -> bool {
+        self.ppu.take_nmi()
+    }
+}
+
+bitflags! {
+    /// A standard controller's buttons, in the order it reports them.
+    pub struct Buttons: u8 {
+        const A      = 0b0000_0001;
+        const B      = 0b0000_0010;
+        const SELECT = 0b0000_0100;
+        const START  = 0b0000_1000;
+        const UP     = 0b0001_0000;
+        const DOWN   = 0b0010_0000;
+        const LEFT   = 0b0100_0000;
+        const RIGHT  = 0b1000_0000;
+    }
+}
+
+/// Two standard controllers at $4016 and $4017. While bit 0 of $4016 is
+/// set their shift registers keep reloading; once it's cleared each read
+/// shifts out the next button.
+pub struct Controllers {
+    buttons: [Buttons; 2],
+    shift: [u8; 2],
+    strobe: bool,
+}
+
+impl Default for Controllers {
+    fn default() -> Self {
+        Controllers::new()
+    }
+}
+
+impl Controllers {
+    pub fn new() -> Controllers {
+        Controllers { buttons: [Buttons::empty(); 2], shift: [0; 2], strobe: false }
+    }
+
+    pub fn press(&mut self, player: usize, buttons: Buttons) {
+        self.buttons[player].insert(buttons);
+        self.reload();
+    }
+
+    pub fn release(&mut self, player: usize, buttons: Buttons) {
+        self.buttons[player].remove(buttons);
+        self.reload();
+    }
+
+    fn reload(&mut self) {
+        if self.strobe {
+            self.shift = self.buttons.map(|buttons| buttons.bits());
+        }
+    }
+}
+
+/// Only the controller ports; the APU registers read as open bus.
+impl Device for Controllers {
+    fn read(&mut self, addr: u16) -> Option<u8> {
+        let val = self.peek(addr)?;
+        let port = addr as usize - 0x4016;
+        if !self.strobe {
+            // after eight reads only ones come out
+            self.shift[port] = self.shift[port] >> 1 | 0x80;
+        }
+        Some(val)
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        if addr == 0x4016 {
+            self.strobe = val & 1 != 0;
+            self.reload();
+        }
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        let port = match addr {
+            0x4016 | 0x4017 => addr as usize - 0x4016,
+            _ => return None,
+        };
+        let bit = if self.strobe { self.buttons[port].bits() } else { self.shift[port] } & 1;
+        // the upper bits are open bus, nearly always $40 from the address
+        Some(0x40 | bit)
+    }
+}
+
+/// The Nintendo Entertainment System, built around a `CPU` like the
+/// other machines.
+pub struct Nes {
+    cartridge: Rc<RefCell<Cartridge>>,
+    ppu: Rc<RefCell<Ppu>>,
+    controllers: Rc<RefCell<Controllers>>,
+    screen: Rc<RefCell<Framebuffer>>,
+    palette: Palette,
+    #[cfg(feature = "std")]
+    filter: Option<NtscFilter>,
+}
+
+impl Nes {
+    /// Puts `cpu` on the console's bus with `cartridge` plugged in, timed
+    /// for the cartridge's region. Reset the CPU afterwards to boot the
+    /// game.
+    pub fn attach(cpu: &mut CPU, cartridge: Cartridge) -> Result<Nes, CartridgeError> {
+        cartridge.check_supported()?;
+        let timing = cartridge.timing;
+        let cartridge = Rc::new(RefCell::new(cartridge));
+        let ppu = Rc::new(RefCell::new(Ppu::new(cartridge.clone())));
+        let controllers = Rc::new(RefCell::new(Controllers::new()));
+        let mut bus = NesBus::new();
+        bus.set_ppu(ppu.clone());
+        bus.set_io(controllers.clone());
+        bus.set_cartridge(cartridge.clone());
+        cpu.bus = Bus::with_memory(bus);
+        cpu.set_frame_cycles(timing.frame_cycles());
+        let screen = Rc::new(RefCell::new(Framebuffer::new(WIDTH, HEIGHT)));
+        Ok(Nes {
+            cartridge,
+            ppu,
+            controllers,
+            screen,
+            palette: Palette::default(),
+            #[cfg(feature = "std")]
+            filter: None,
+        })
+    }
+
+    pub fn cartridge(&self) -> Ref<'_, Cartridge> {
+        self.cartridge.borrow()
+    }
+
+    pub fn ppu(&self) -> Ref<'_, Ppu> {
+        self.ppu.borrow()
+    }
+
+    /// Every address the CPU sees the first byte of PRG-ROM at: $8000,
+    /// and $C000 too for a 16K ROM.
+    pub fn prg_bases(&self) -> Vec<u16> {
+        let len = self.cartridge.borrow().prg_rom.len().clamp(1, 0x8000);
+        (0x8000..=0xffff).step_by(len).collect()
+    }
+
+    /// Changes the colours the screen is drawn in, from the next frame.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    /// Draws the screen through an NTSC filter, which makes it
+    /// [`FILTER_WIDTH`] by [`FILTER_HEIGHT`], or without one at the PPU's
+    /// own size.
+    #[cfg(feature = "std")]
+    pub fn set_filter(&mut self, filter: Option<NtscFilter>) {
+        let (width, height) = if filter.is_some() { (FILTER_WIDTH, FILTER_HEIGHT) } else { (WIDTH, HEIGHT) };
+        *self.screen.borrow_mut() = Framebuffer::new(width, height);
+        self.filter = filter;
+    }
+
+    pub fn set_renderer(&self, renderer: Renderer) {
+        self.ppu.borrow_mut().set_renderer(renderer);
+    }
+
+    pub fn controllers(&self) -> RefMut<'_, Controllers> {
+        self.controllers.borrow_mut()
+    }
+
+    /// The last frame the PPU finished.
+    pub fn screen(&self) -> RefMut<'_, Framebuffer> {
+        let mut screen = self.screen.borrow_mut();
+        let mut ppu = self.ppu.borrow_mut();
+        if ppu.take_frame() {
+            #[cfg(feature = "std")]
+            if let Some(filter) = &self.filter {
+                filter.apply(ppu.pixels(), ppu.frame(), &mut screen);
+                return screen;
+            }
+            for (i, &pixel) in ppu.pixels().iter().enumerate() {
+                screen.set_pixel(i % WIDTH, i / WIDTH, self.palette.rgb(pixel));
+            }
+        }
+        screen
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge;
+    use crate::flow::FlowAnalyzer;
+    use crate::sanitizer::SanitizerConfig;
+    use crate::StopReason;
+
+    /// 32K of PRG-ROM at $8000.
+    struct Rom(Vec<u8>);
+
+    impl Device for Rom {
+        fn read(&mut self, addr: u16) -> Option<u8> {
+            self.peek(addr)
+        }
+
+        fn write(&mut self, _addr: u16, _val: u8) {}
+
+        fn peek(&self, addr: u16) -> Option<u8> {
+            (addr >= 0x8000).then(|| self.0[addr as usize - 0x8000])
+        }
+    }
+
+    #[derive(Default)]
+    struct Registers {
+        writes: Vec<(u16, u8)>,
+        /// Cycles ticked so far, and how many had been at each write.
+        ticked: u64,
+        write_cycles: Vec<u64>,
+    }
+
+    impl Device for Registers {
+        fn read(&mut self, addr: u16) -> Option<u8> {
+            Some(addr as u8)
+        }
+
+        fn write(&mut self, addr: u16, val: u8) {
+            self.writes.push((addr, val));
+            self.write_cycles.push(self.ticked);
+        }
+
+        fn peek(&self, addr: u16) -> Option<u8> {
+            Some(addr as u8)
+        }
+
+        fn tick(&mut self, cycles: u64) {
+            self.ticked += cycles;
+        }
+    }
+
+    #[test]
+    fn test_ram_and_register_mirrors() {
+        let mut bus = NesBus::new();
+        let ppu = Rc::new(RefCell::new(Registers::default()));
+        bus.set_ppu(ppu.clone());
+        bus.write(0x0801, 0x42);
+        assert_eq!(bus.read(0x0001), 0x42);
+        assert_eq!(bus.read(0x1801), 0x42);
+        assert_eq!(bus.peek(0x1001), 0x42);
+
+        bus.write(0x2000, 1);
+        bus.write(0x3ff9, 2);
+        assert_eq!(ppu.borrow().writes, vec![(0x2000, 1), (0x2001, 2)]);
+        assert_eq!(bus.read(0x200a), 0x02);
+    }
+
+    #[test]
+    fn test_open_bus() {
+        let mut bus = NesBus::new();
+        bus.write(0x0010, 0x5a);
+        assert_eq!(bus.read(0x0010), 0x5a);
+        // nothing answers in test-mode or empty cartridge space
+        assert_eq!(bus.read(0x4018), 0x5a);
+        assert_eq!(bus.read(0x6000), 0x5a);
+        bus.write(0x4400, 0x17);
+        assert_eq!(bus.read(0x401f), 0x17);
+        assert_eq!(bus.peek(0x8000), 0x17);
+    }
+
+    #[test]
+    fn test_cpu_runs_on_the_nes_bus() {
+        let mut rom = vec![0xea; 0x8000];
+        // LDA #$42; STA $0805; LDX $0005; LDA $4018; BRK
+        rom[..11].copy_from_slice(&[0xa9, 0x42, 0x8d, 0x05, 0x08, 0xae, 0x05, 0x00, 0xad, 0x18, 0x40]);
+        rom[11] = 0x00;
+        rom[0x7ffc..0x7ffe].copy_from_slice(&[0x00, 0x80]);
+        let mut bus = NesBus::new();
+        bus.set_cartridge(Rom(rom));
+
+        let mut cpu = CPU::with_bus(Bus::with_memory(bus));
+        cpu.reset();
+        assert_eq!(cpu.pc, 0x8000);
+        assert_eq!(cpu.run(), StopReason::Break);
+        assert_eq!(cpu.rx, 0x42);
+        // the operand's high byte was the last thing on the bus
+        assert_eq!(cpu.ra, 0x40);
+    }
+
+    #[test]
+    fn test_oam_dma_stalls_the_cpu() {
+        let mut rom = vec![0xea; 0x8000];
+        // LDA #$02; STA $4014; NOP; STA $4014
+        rom[..9].copy_from_slice(&[0xa9, 0x02, 0x8d, 0x14, 0x40, 0xea, 0x8d, 0x14, 0x40]);
+        rom[0x7ffc..0x7ffe].copy_from_slice(&[0x00, 0x80]);
+        let ppu = Rc::new(RefCell::new(Registers::default()));
+        let mut bus = NesBus::new();
+        bus.set_ppu(ppu.clone());
+        bus.set_cartridge(Rom(rom));
+        let mut cpu = CPU::with_bus(Bus::with_memory(bus));
+        for i in 0..=0xff {
+            cpu.mem_write(0x0200 + i, i as u8 ^ 0x5a);
+        }
+        ppu.borrow_mut().writes.clear();
+        cpu.reset();
+
+        // the write lands on even cycle 12
+        cpu.run_for_instructions(2);
+        assert_eq!(cpu.cycles(), 7 + 2 + 4 + 513);
+        let writes = ppu.borrow_mut().writes.split_off(0);
+        assert_eq!(writes.len(), 256);
+        assert!(writes.iter().enumerate().all(|(i, &write)| write == (0x2004, i as u8 ^ 0x5a)));
+
+        // and then on odd cycle 531
+        cpu.run_for_instructions(2);
+        assert_eq!(cpu.cycles(), 526 + 2 + 4 + 514);
+    }
+
+    #[test]
+    fn test_devices_are_caught_up_to_each_access() {
+        let mut rom = vec![0xea; 0x8000];
+        // LDA #$02; STA $2000; NOP; STA $2001
+        rom[..9].copy_from_slice(&[0xa9, 0x02, 0x8d, 0x00, 0x20, 0xea, 0x8d, 0x01, 0x20]);
+        rom[0x7ffc..0x7ffe].copy_from_slice(&[0x00, 0x80]);
+        let ppu = Rc::new(RefCell::new(Registers::default()));
+        let mut bus = NesBus::new();
+        bus.set_ppu(ppu.clone());
+        bus.set_cartridge(Rom(rom));
+        let mut cpu = CPU::with_bus(Bus::with_memory(bus));
+        cpu.reset();
+
+        // each store's write is its fourth cycle
+        cpu.run_for_instructions(4);
+        assert_eq!(ppu.borrow().write_cycles, [2 + 3, 2 + 4 + 2 + 3]);
+        assert_eq!(ppu.borrow().ticked, 2 + 4 + 2 + 4);
+    }
+
+    #[test]
+    fn test_attach_boots_the_cartridge() {
+        // LDA #$07; STA $6000; STA $2003; STA $3ffc; BRK
+        let program = [0xa9, 0x07, 0x8d, 0x00, 0x60, 0x8d, 0x03, 0x20, 0x8d, 0xfc, 0x3f, 0x00];
+        let mut cpu = CPU::new();
+        let nes = Nes::attach(&mut cpu, nrom(&program, &[0x40])).unwrap();
+        cpu.reset();
+        assert_eq!(cpu.pc, 0xc000);
+        assert_eq!(cpu.run(), StopReason::Break);
+        assert_eq!(nes.cartridge().prg_ram[0], 0x07);
+        assert_eq!(nes.ppu().oam()[0x07], 0x07);
+        assert_eq!(nes.screen().width(), WIDTH);
+    }
+
+    #[test]
+    fn test_vblank_nmi_runs_the_handler() {
+        // LDA #$80; STA $2000; JMP $c005
+        let program = [0xa9, 0x80, 0x8d, 0x00, 0x20, 0x4c, 0x05, 0xc0];
+        // INC $00; RTI
+        let handler = [0xe6, 0x00, 0x40];
+        let mut cpu = CPU::new();
+        let _nes = Nes::attach(&mut cpu, nrom(&program, &handler)).unwrap();
+        cpu.reset();
+        for frame in 1..=3 {
+            cpu.run_frame();
+            assert_eq!(cpu.mem_peek(0x0000), frame);
+        }
+    }
+
+    #[test]
+    fn test_sanitizer_accepts_rom_and_registers() {
+        // LDA #$80; STA $2000; LDA $2002; JMP $c008
+        let program = [0xa9, 0x80, 0x8d, 0x00, 0x20, 0xad, 0x02, 0x20, 0x4c, 0x08, 0xc0];
+        let mut cpu = CPU::new();
+        let _nes = Nes::attach(&mut cpu, nrom(&program, &[0x40])).unwrap();
+        cpu.enable_sanitizer(SanitizerConfig::strict());
+        cpu.reset();
+        assert_eq!(cpu.run_frame(), StopReason::BudgetExhausted);
+        assert!(cpu.sanitizer().unwrap().findings().is_empty());
+    }
+
+    #[test]
+    fn test_code_log_is_indexed_from_prg_rom() {
+        let mut cartridge = nrom(&[], &[0x40]);
+        // LDA #$01; BRK at $c123, reset vector pointing at it and no IRQ
+        cartridge.prg_rom[0x123..0x126].copy_from_slice(&[0xa9, 0x01, 0x00]);
+        cartridge.prg_rom[0x3ffc..0x4000].copy_from_slice(&[0x23, 0xc1, 0x00, 0x00]);
+        cartridge.prg_rom[0x200] = 0x60;
+        let mut cpu = CPU::new();
+        let nes = Nes::attach(&mut cpu, cartridge).unwrap();
+        cpu.reset();
+        assert_eq!((cpu.pc, nes.prg_bases()), (0xc123, vec![0x8000, 0xc000]));
+
+        // the log marks a lone RTS at PRG offset $200 as code, and all of
+        // CHR-ROM after it as rendered, which shares the bit
+        let mut cdl = vec![0; 0x4000];
+        cdl[0x200] = 0x01;
+        cdl.extend([0x01; 0x2000]);
+        let mut analyzer = FlowAnalyzer::new(|addr| cpu.mem_peek(addr));
+        analyzer.add_vectors();
+        analyzer.set_cdl(cdl, nes.cartridge().prg_rom.len(), &nes.prg_bases());
+        let cfg = analyzer.analyze();
+        assert_eq!(cfg.functions.keys().copied().collect::<Vec<_>>(), vec![0x8200, 0xc100, 0xc123, 0xc200]);
+    }
+
+    #[test]
+    fn test_controller_shift_register() {
+        let mut controllers = Controllers::new();
+        controllers.press(0, Buttons::A | Buttons::START);
+        controllers.write(0x4016, 1);
+        controllers.press(0, Buttons::B);
+        controllers.write(0x4016, 0);
+        controllers.release(0, Buttons::A);
+
+        let bits: Vec<u8> = (0..10).map(|_| controllers.read(0x4016).unwrap() & 1).collect();
+        assert_eq!(bits, [1, 1, 0, 1, 0, 0, 0, 0, 1, 1]);
+        assert_eq!(controllers.read(0x4017), Some(0x40));
+        assert_eq!(controllers.read(0x4015), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_ntsc_filter_widens_the_screen() {
+        use crate::ntsc::Preset;
+        use crate::palette::NtscSettings;
+
+        let mut cpu = CPU::new();
+        let mut nes = Nes::attach(&mut cpu, nrom(&[0x4c, 0x00, 0xc0], &[0x40])).unwrap();
+        cpu.reset();
+        nes.set_filter(Some(NtscFilter::new(Preset::Composite, NtscSettings::default(), Palette::default())));
+        cpu.run_frame();
+        {
+            let mut screen = nes.screen();
+            assert_eq!((screen.width(), screen.height()), (FILTER_WIDTH, FILTER_HEIGHT));
+            assert!(screen.take_dirty_rows().is_some());
+        }
+        nes.set_filter(None);
+        assert_eq!(nes.screen().width(), WIDTH);
+    }
+
+    /// An NROM cartridge booting into `program` at $C000, with its NMI
+    /// handler at $C100.
+    fn nrom(program: &[u8], handler: &[u8]) -> Cartridge {
+        let mut cartridge = Cartridge::parse(&cartridge::ines(1, 1, 0, 0)).unwrap();
+        let prg = &mut cartridge.prg_rom;
+        prg.fill(0xea);
+        prg[..program.len()].copy_from_slice(program);
+        prg[0x100..0x100 + handler.len()].copy_from_slice(handler);
+        prg[0x3ffa..0x3ffe].copy_from_slice(&[0x00, 0xc1, 0x00, 0xc0]);
+        cartridge.chr.fill(0);
+        cartridge
+    }
+}
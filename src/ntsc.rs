@@ -0,0 +1,274 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::f64::consts::PI;
+
+use crate::framebuffer::Framebuffer;
+use crate::nes::{HEIGHT, WIDTH};
+use crate::palette::{self, NtscSettings, Palette, COLORS};
+use crate::random::{RandomSource, Xorshift};
+
+/// Composite signal samples per PPU pixel; the colour subcarrier takes
+/// 12.
+const SAMPLES_PER_PIXEL: usize = 8;
+const SAMPLES_PER_CYCLE: usize = 12;
+const LINE_SAMPLES: usize = WIDTH * SAMPLES_PER_PIXEL;
+/// Steps in the gamma lookup table between black and full brightness.
+const GAMMA_STEPS: usize = 4096;
+/// Output pixels per PPU pixel, across and down.
+const SCALE: usize = 2;
+/// Samples each output pixel stands for.
+const STEP: usize = SAMPLES_PER_PIXEL / SCALE;
+
+/// The filtered picture's size: twice the PPU's across, for the extra
+/// horizontal detail a TV shows, and twice down to keep the shape.
+pub const FILTER_WIDTH: usize = WIDTH * SCALE;
+pub const FILTER_HEIGHT: usize = HEIGHT * SCALE;
+
+/// The cable between console and TV.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    /// Composite through a TV's tuner: blurrier, with noise and a ghost.
+    Rf,
+    /// Luma and chroma share a wire, so each bleeds into the other.
+    Composite,
+    /// Separate luma and chroma: no dot crawl, just soft colour edges.
+    SVideo,
+    /// The palette's colours, untouched.
+    Rgb,
+}
+
+impl Preset {
+    pub fn from_name(name: &str) -> Option<Preset> {
+        match name {
+            "rf" => Some(Preset::Rf),
+            "composite" => Some(Preset::Composite),
+            "svideo" | "s-video" => Some(Preset::SVideo),
+            "rgb" => Some(Preset::Rgb),
+            _ => None,
+        }
+    }
+}
+
+/// Encodes frames of 9-bit PPU pixels as the NTSC signal the console
+/// sends and decodes them again the way a TV would, artifacts and all.
+/// The subcarrier's phase moves 4/12 of a cycle each line and alternates
+/// between frames, which makes the dot crawl.
+pub struct NtscFilter {
+    preset: Preset,
+    settings: NtscSettings,
+    /// Each colour's signal at the 12 subcarrier phases, and its luma.
+    signals: Vec<[f64; SAMPLES_PER_CYCLE]>,
+    lumas: Vec<f64>,
+    /// The subcarrier at each phase, turned by the hue setting.
+    carrier: [(f64, f64); SAMPLES_PER_CYCLE],
+    /// [`palette::gamma`] at each step, which is too slow to call for
+    /// every pixel.
+    gamma: Vec<u8>,
+    palette: Palette,
+}
+
+impl NtscFilter {
+    /// `palette` is what [`Preset::Rgb`] draws in; the other presets
+    /// decode the PPU's own signal.
+    pub fn new(preset: Preset, settings: NtscSettings, palette: Palette) -> NtscFilter {
+        let signals: Vec<[f64; SAMPLES_PER_CYCLE]> = (0..COLORS as u16)
+            .map(|pixel| core::array::from_fn(|phase| palette::signal(pixel, phase)))
+            .collect();
+        let lumas = signals.iter().map(|levels| levels.iter().sum::<f64>() / SAMPLES_PER_CYCLE as f64).collect();
+        let carrier = core::array::from_fn(|phase| {
+            let angle = PI * phase as f64 / 6.0 + palette::PHASE_OFFSET + settings.hue.to_radians();
+            (angle.cos(), angle.sin())
+        });
+        let gamma = (0..=GAMMA_STEPS).map(|step| palette::gamma(step as f64 / GAMMA_STEPS as f64)).collect();
+        NtscFilter { preset, settings, signals, lumas, carrier, gamma, palette }
+    }
+
+    pub fn preset(&self) -> Preset {
+        self.preset
+    }
+
+    /// Draws `pixels`, one PPU frame, onto `screen`, which must be
+    /// [`FILTER_WIDTH`] by [`FILTER_HEIGHT`]. `frame` picks the phase.
+    pub fn apply(&self, pixels: &[u16], frame: u64, screen: &mut Framebuffer) {
+        let mut noise = Xorshift::new(frame + 1);
+        let mut line = vec![0.0; LINE_SAMPLES];
+        for y in 0..HEIGHT {
+            let row = &pixels[y * WIDTH..(y + 1) * WIDTH];
+            if self.preset == Preset::Rgb {
+                for (x, &pixel) in row.iter().enumerate() {
+                    self.put(screen, x * SCALE, y, [self.palette.rgb(pixel); SCALE]);
+                }
+                continue;
+            }
+            // a skipped dot on odd frames puts them 4 phases out, and
+            // each line starts 4 phases after the last
+            let phase = (frame as usize % 2 * 4 + y * 4) % SAMPLES_PER_CYCLE;
+            self.encode(row, phase, &mut line);
+            if self.preset == Preset::Rf {
+                degrade(&mut line, &mut noise);
+            }
+            self.decode(row, phase, &line, y, screen);
+        }
+    }
+
+    /// The composite signal for one line of pixels.
+    fn encode(&self, row: &[u16], phase: usize, line: &mut [f64]) {
+        for (s, sample) in line.iter_mut().enumerate() {
+            let pixel = row[s / SAMPLES_PER_PIXEL] as usize % COLORS;
+            *sample = self.signals[pixel][(phase + s) % SAMPLES_PER_CYCLE];
+        }
+    }
+
+    fn decode(&self, row: &[u16], phase: usize, line: &[f64], y: usize, screen: &mut Framebuffer) {
+        let (luma_window, chroma_window) = match self.preset {
+            Preset::Rf => (18, 36),
+            Preset::Composite => (12, 24),
+            _ => (4, 24),
+        };
+        let luma: Vec<f64> = match self.preset {
+            // S-Video's luma wire carries no subcarrier
+            Preset::SVideo => (0..LINE_SAMPLES).map(|s| self.lumas[row[s / SAMPLES_PER_PIXEL] as usize % COLORS]).collect(),
+            _ => line.to_vec(),
+        };
+        let chroma: Vec<f64> = match self.preset {
+            Preset::SVideo => line.iter().zip(&luma).map(|(signal, luma)| signal - luma).collect(),
+            _ => line.to_vec(),
+        };
+        // running sums make every window's average one subtraction
+        let luma = running_sums(luma.iter().copied());
+        let i = running_sums(chroma.iter().enumerate().map(|(s, level)| level * self.carrier[(phase + s) % SAMPLES_PER_CYCLE].0));
+        let q = running_sums(chroma.iter().enumerate().map(|(s, level)| level * self.carrier[(phase + s) % SAMPLES_PER_CYCLE].1));
+
+        let mut rgb = [[0; 3]; FILTER_WIDTH];
+        for (k, out) in rgb.iter_mut().enumerate() {
+            let center = k * STEP + STEP / 2;
+            let y = window(&luma, center, luma_window) * self.settings.contrast + self.settings.brightness;
+            let saturation = self.settings.saturation;
+            let i = window(&i, center, chroma_window) * saturation;
+            let q = window(&q, center, chroma_window) * saturation;
+            *out = palette::yiq_to_linear(y, i, q).map(|value| {
+                self.gamma[(value.clamp(0.0, 1.0) * GAMMA_STEPS as f64).round() as usize]
+            });
+        }
+        for (x, pair) in rgb.chunks_exact(SCALE).enumerate() {
+            self.put(screen, x * SCALE, y, pair.try_into().unwrap());
+        }
+    }
+
+    /// Sets the output pixels from `x` on both rows PPU line `y` becomes.
+    fn put(&self, screen: &mut Framebuffer, x: usize, y: usize, colors: [[u8; 3]; SCALE]) {
+        for (column, &color) in colors.iter().enumerate() {
+            for row in 0..SCALE {
+                screen.set_pixel(x + column, y * SCALE + row, color);
+            }
+        }
+    }
+}
+
+/// What a TV tuner adds: noise, and a faint echo of the picture a little
+/// to the right.
+fn degrade(line: &mut [f64], noise: &mut Xorshift) {
+    const GHOST_DELAY: usize = 20;
+    for s in (GHOST_DELAY..line.len()).rev() {
+        line[s] += line[s - GHOST_DELAY] * 0.08;
+    }
+    for sample in line.iter_mut() {
+        *sample += (noise.next_byte() as f64 - 127.5) / 127.5 * 0.03;
+    }
+}
+
+/// `sums[n]` is the total of the first `n` values.
+fn running_sums(values: impl Iterator<Item = f64>) -> Vec<f64> {
+    let mut sums = vec![0.0];
+    let mut total = 0.0;
+    for value in values {
+        total += value;
+        sums.push(total);
+    }
+    sums
+}
+
+/// The mean of the `width` values centred on `center`, moved inside the
+/// line near its ends.
+fn window(sums: &[f64], center: usize, width: usize) -> f64 {
+    let start = center.saturating_sub(width / 2);
+    let end = (start + width).min(sums.len() - 1);
+    let start = end.saturating_sub(width);
+    (sums[end] - sums[start]) / (end - start) as f64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn filtered(preset: Preset, pixels: &[u16], frame: u64) -> Framebuffer {
+        let mut screen = Framebuffer::new(FILTER_WIDTH, FILTER_HEIGHT);
+        let settings = NtscSettings::default();
+        NtscFilter::new(preset, settings, Palette::generate(settings)).apply(pixels, frame, &mut screen);
+        screen
+    }
+
+    fn rgb(screen: &Framebuffer, x: usize, y: usize) -> [u8; 3] {
+        let offset = y * screen.pitch() + x * 3;
+        screen.pixels()[offset..offset + 3].try_into().unwrap()
+    }
+
+    fn distance(a: [u8; 3], b: [u8; 3]) -> i32 {
+        a.iter().zip(&b).map(|(&a, &b)| (a as i32 - b as i32).abs()).sum()
+    }
+
+    #[test]
+    fn test_flat_colours_decode_to_the_palette() {
+        let palette = Palette::generate(NtscSettings::default());
+        for pixel in [0x16, 0x2a, 0x30, 0x12 | 0x40] {
+            let pixels = vec![pixel; WIDTH * HEIGHT];
+            for preset in [Preset::Composite, Preset::SVideo, Preset::Rgb] {
+                let screen = filtered(preset, &pixels, 0);
+                let color = rgb(&screen, FILTER_WIDTH / 2, 100);
+                assert!(distance(color, palette.rgb(pixel)) <= 3, "{:?} {:03x}: {:?}", preset, pixel, color);
+            }
+        }
+    }
+
+    #[test]
+    fn test_composite_artifacts() {
+        // one-pixel stripes of white and black
+        let pixels: Vec<u16> = (0..WIDTH * HEIGHT).map(|i| if i % 2 == 0 { 0x30 } else { 0x0f }).collect();
+        let colorfulness = |color: [u8; 3]| color.iter().max().unwrap() - color.iter().min().unwrap();
+        let composite = filtered(Preset::Composite, &pixels, 0);
+        let svideo = filtered(Preset::SVideo, &pixels, 0);
+        let rgb_out = filtered(Preset::Rgb, &pixels, 0);
+        assert!((100..140).any(|x| colorfulness(rgb(&composite, x, 50)) > 20));
+        assert!((100..140).all(|x| colorfulness(rgb(&svideo, x, 50)) < 4));
+        assert!((100..140).all(|x| colorfulness(rgb(&rgb_out, x, 50)) == 0));
+
+        // the pattern crawls between frames but repeats every other one
+        assert!(composite.pixels() != filtered(Preset::Composite, &pixels, 1).pixels());
+        assert!(composite.pixels() == filtered(Preset::Composite, &pixels, 2).pixels());
+        assert!(rgb_out.pixels() == filtered(Preset::Rgb, &pixels, 1).pixels());
+        // and so do its colours between lines
+        assert!(rgb(&composite, 120, 0) != rgb(&composite, 120, 2));
+        assert_eq!(rgb(&composite, 120, 0), rgb(&composite, 120, 1));
+    }
+
+    #[test]
+    fn test_rgb_uses_the_given_palette() {
+        let mut bytes = vec![0; 64 * 3];
+        bytes[0x21 * 3..0x21 * 3 + 3].copy_from_slice(&[0x12, 0x34, 0x56]);
+        let custom = Palette::parse(&bytes).unwrap();
+        let pixels = vec![0x21; WIDTH * HEIGHT];
+        let mut screen = Framebuffer::new(FILTER_WIDTH, FILTER_HEIGHT);
+        NtscFilter::new(Preset::Rgb, NtscSettings::default(), custom).apply(&pixels, 0, &mut screen);
+        assert_eq!(rgb(&screen, 10, 10), [0x12, 0x34, 0x56]);
+        assert!(screen.pixels() != filtered(Preset::Rgb, &pixels, 0).pixels());
+    }
+
+    #[test]
+    fn test_rf_is_noisy() {
+        let pixels = vec![0x21; WIDTH * HEIGHT];
+        let rf = filtered(Preset::Rf, &pixels, 0);
+        assert!(rf.pixels() != filtered(Preset::Composite, &pixels, 0).pixels());
+        assert!(rf.pixels() != filtered(Preset::Rf, &pixels, 1).pixels());
+        assert!(rf.pixels() == filtered(Preset::Rf, &pixels, 0).pixels());
+    }
+}
@@ -0,0 +1,251 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+/// The 2C02's 64 colours as commonly measured from an NTSC console.
+/// Entries $0D-$0F, $1D-$1F, $2E-$2F and $3E-$3F are black.
+pub const NTSC: [[u8; 3]; 64] = [
+    [0x54, 0x54, 0x54], [0x00, 0x1e, 0x74], [0x08, 0x10, 0x90], [0x30, 0x00, 0x88],
+    [0x44, 0x00, 0x64], [0x5c, 0x00, 0x30], [0x54, 0x04, 0x00], [0x3c, 0x18, 0x00],
+    [0x20, 0x2a, 0x00], [0x08, 0x3a, 0x00], [0x00, 0x40, 0x00], [0x00, 0x3c, 0x00],
+    [0x00, 0x32, 0x3c], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0x98, 0x96, 0x98], [0x08, 0x4c, 0xc4], [0x30, 0x32, 0xec], [0x5c, 0x1e, 0xe4],
+    [0x88, 0x14, 0xb0], [0xa0, 0x14, 0x64], [0x98, 0x22, 0x20], [0x78, 0x3c, 0x00],
+    [0x54, 0x5a, 0x00], [0x28, 0x72, 0x00], [0x08, 0x7c, 0x00], [0x00, 0x76, 0x28],
+    [0x00, 0x66, 0x78], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xec, 0xee, 0xec], [0x4c, 0x9a, 0xec], [0x78, 0x7c, 0xec], [0xb0, 0x62, 0xec],
+    [0xe4, 0x54, 0xec], [0xec, 0x58, 0xb4], [0xec, 0x6a, 0x64], [0xd4, 0x88, 0x20],
+    [0xa0, 0xaa, 0x00], [0x74, 0xc4, 0x00], [0x4c, 0xd0, 0x20], [0x38, 0xcc, 0x6c],
+    [0x38, 0xb4, 0xcc], [0x3c, 0x3c, 0x3c], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xec, 0xee, 0xec], [0xa8, 0xcc, 0xec], [0xbc, 0xbc, 0xec], [0xd4, 0xb2, 0xec],
+    [0xec, 0xae, 0xec], [0xec, 0xae, 0xd4], [0xec, 0xb4, 0xb0], [0xe4, 0xc4, 0x90],
+    [0xcc, 0xd2, 0x78], [0xb4, 0xde, 0x78], [0xa8, 0xe2, 0x90], [0x98, 0xe2, 0xb4],
+    [0xa0, 0xd6, 0xe4], [0xa0, 0xa2, 0xa0], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+];
+
+/// Colours the PPU can produce: 64 palette entries under each of the
+/// 8 combinations of PPUMASK's emphasis bits.
+pub const COLORS: usize = 512;
+
+/// How much an emphasis bit dims the colours it doesn't favour, out of 256.
+const ATTENUATION: u16 = 191;
+
+#[derive(Debug)]
+pub enum PaletteError {
+    /// Palette files hold 64 or 512 RGB triples.
+    BadSize(usize),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaletteError::BadSize(size) => {
+                write!(f, "a palette file is 192 or 1536 bytes, not {}", size)
+            }
+        }
+    }
+}
+
+/// Maps the PPU's 9-bit pixels, a palette entry with the emphasis bits
+/// above it, to RGB.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+}
+
+impl Default for Palette {
+    /// [`NTSC`], emphasised by dimming.
+    fn default() -> Self {
+        Palette::from_base(&NTSC)
+    }
+}
+
+impl Palette {
+    /// Reads a `.pal` file: 64 colours, whose emphasised versions are
+    /// made by dimming, or all 512.
+    pub fn parse(bytes: &[u8]) -> Result<Palette, PaletteError> {
+        let colors: Vec<[u8; 3]> = bytes.chunks_exact(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]).collect();
+        match bytes.len() {
+            192 => Ok(Palette::from_base(&colors)),
+            1536 => Ok(Palette { colors }),
+            size => Err(PaletteError::BadSize(size)),
+        }
+    }
+
+    /// Extends 64 colours to 512 the way the 2C02's emphasis works: each
+    /// bit dims the two colour channels it doesn't name.
+    fn from_base(base: &[[u8; 3]]) -> Palette {
+        let colors = (0..COLORS)
+            .map(|pixel| {
+                let emphasis = pixel >> 6;
+                let mut rgb = base[pixel & 0x3f];
+                for (channel, value) in rgb.iter_mut().enumerate() {
+                    if emphasis & !(1 << channel) != 0 {
+                        *value = (*value as u16 * ATTENUATION / 256) as u8;
+                    }
+                }
+                rgb
+            })
+            .collect();
+        Palette { colors }
+    }
+
+    /// The colour of a pixel the PPU produced.
+    pub fn rgb(&self, pixel: u16) -> [u8; 3] {
+        self.colors[pixel as usize % COLORS]
+    }
+
+    /// All 512 colours in `.pal` order.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.colors.iter().flatten().copied().collect()
+    }
+}
+
+/// Knobs for [`Palette::generate`]; the defaults give a palette close
+/// to [`NTSC`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NtscSettings {
+    /// Degrees to turn every hue by.
+    pub hue: f64,
+    pub saturation: f64,
+    pub contrast: f64,
+    /// Added to the luma, where 1.0 is the distance from black to white.
+    pub brightness: f64,
+}
+
+impl Default for NtscSettings {
+    fn default() -> Self {
+        NtscSettings { hue: 0.0, saturation: 1.0, contrast: 1.0, brightness: 0.0 }
+    }
+}
+
+/// The PPU's output for `pixel` at each of the 12 phases of a colour
+/// subcarrier cycle, from 0.0 for black to 1.0 for white.
+#[cfg(feature = "std")]
+pub(crate) fn signal(pixel: u16, phase: usize) -> f64 {
+    // the 2C02's four luma levels, in volts, at the low and high points
+    // of its square wave
+    const LOW_LEVELS: [f64; 4] = [0.350, 0.518, 0.962, 1.550];
+    const HIGH_LEVELS: [f64; 4] = [1.094, 1.506, 1.962, 1.962];
+    const BLACK: f64 = 0.518;
+    const WHITE: f64 = 1.962;
+    const EMPHASIS_ATTENUATION: f64 = 0.746;
+
+    let hue = (pixel & 0x0f) as usize;
+    let emphasis = pixel >> 6 & 7;
+    // columns $E and $F are black whatever the row says
+    let level = if hue > 0x0d { 1 } else { (pixel >> 4 & 3) as usize };
+    let in_phase = |hue: usize| (hue + phase) % 12 < 6;
+    let mut volts = match hue {
+        0x00 => HIGH_LEVELS[level],
+        0x0d..=0x0f => LOW_LEVELS[level],
+        _ if in_phase(hue) => HIGH_LEVELS[level],
+        _ => LOW_LEVELS[level],
+    };
+    // red, green and blue emphasis are active in the phases of hues
+    // $C, $4 and $8
+    if (0..3).any(|bit| emphasis & 1 << bit != 0 && in_phase([0x0c, 0x04, 0x08][bit])) {
+        volts *= EMPHASIS_ATTENUATION;
+    }
+    (volts - BLACK) / (WHITE - BLACK)
+}
+
+/// Where the subcarrier's phase 0 falls relative to a TV's colour burst.
+#[cfg(feature = "std")]
+pub(crate) const PHASE_OFFSET: f64 = 120.0 * core::f64::consts::PI / 180.0;
+
+/// The RGB a TV shows for a YIQ colour, before gamma correction.
+#[cfg(feature = "std")]
+pub(crate) fn yiq_to_linear(y: f64, i: f64, q: f64) -> [f64; 3] {
+    [
+        y + 0.946882 * i + 0.623557 * q,
+        y - 0.274788 * i - 0.635691 * q,
+        y - 1.108545 * i + 1.709007 * q,
+    ]
+}
+
+/// A channel from [`yiq_to_linear`] as a byte, gamma corrected.
+#[cfg(feature = "std")]
+pub(crate) fn gamma(value: f64) -> u8 {
+    (value.max(0.0).powf(2.2 / 1.8) * 255.0).round().min(255.0) as u8
+}
+
+#[cfg(feature = "std")]
+impl Palette {
+    /// Computes the colours a TV would show by decoding the PPU's
+    /// composite signal for each one.
+    pub fn generate(settings: NtscSettings) -> Palette {
+        let colors = (0..COLORS as u16)
+            .map(|pixel| {
+                let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+                for phase in 0..12 {
+                    let level = signal(pixel, phase);
+                    let angle = core::f64::consts::PI * phase as f64 / 6.0 + PHASE_OFFSET + settings.hue.to_radians();
+                    y += level / 12.0;
+                    i += level * angle.cos() / 12.0;
+                    q += level * angle.sin() / 12.0;
+                }
+                let y = y * settings.contrast + settings.brightness;
+                yiq_to_linear(y, i * settings.saturation, q * settings.saturation).map(gamma)
+            })
+            .collect();
+        Palette { colors }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_emphasis_dims_the_other_channels() {
+        let palette = Palette::default();
+        assert_eq!(palette.rgb(0x30), NTSC[0x30]);
+        let [r, g, b] = NTSC[0x30];
+        let dim = |value: u8| (value as u16 * ATTENUATION / 256) as u8;
+        // red emphasis
+        assert_eq!(palette.rgb(0x70), [r, dim(g), dim(b)]);
+        // green and blue
+        assert_eq!(palette.rgb(0x1b0), [dim(r), dim(g), dim(b)]);
+        assert_eq!(palette.rgb(0x130), [dim(r), dim(g), b]);
+    }
+
+    #[test]
+    fn test_parse_pal_files() {
+        let mut bytes: Vec<u8> = (0..192).map(|i| i as u8).collect();
+        let palette = Palette::parse(&bytes).unwrap();
+        assert_eq!(palette.rgb(0x01), [3, 4, 5]);
+        assert_eq!(palette.rgb(0x41), [3, 2, 3]);
+
+        bytes.resize(1536, 0xaa);
+        let palette = Palette::parse(&bytes).unwrap();
+        assert_eq!(palette.rgb(0x41), [0xaa; 3]);
+        assert_eq!(palette.to_bytes(), bytes);
+
+        assert!(matches!(Palette::parse(&bytes[..100]), Err(PaletteError::BadSize(100))));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_generated_palette_is_close_to_the_measured_one() {
+        let palette = Palette::generate(NtscSettings::default());
+        for entry in 0..64 {
+            let [r, g, b] = palette.rgb(entry);
+            let [nr, ng, nb] = NTSC[entry as usize];
+            let distance = (r as i32 - nr as i32).abs() + (g as i32 - ng as i32).abs() + (b as i32 - nb as i32).abs();
+            assert!(distance < 100, "entry {:02x}: {:?} against {:?}", entry, [r, g, b], [nr, ng, nb]);
+        }
+        // black stays black, greys stay grey
+        assert_eq!(palette.rgb(0x0f), [0, 0, 0]);
+        let [r, g, b] = palette.rgb(0x10);
+        assert!(r == g && g == b);
+
+        // emphasis darkens, settings do what they say
+        let sum = |rgb: [u8; 3]| rgb.iter().map(|&c| c as u32).sum::<u32>();
+        assert!(sum(palette.rgb(0x1f0)) < sum(palette.rgb(0x30)));
+        let grey = Palette::generate(NtscSettings { saturation: 0.0, ..NtscSettings::default() });
+        let [r, g, b] = grey.rgb(0x16);
+        assert!(r == g && g == b);
+        let bright = Palette::generate(NtscSettings { brightness: 0.2, ..NtscSettings::default() });
+        assert!(sum(bright.rgb(0x16)) > sum(palette.rgb(0x16)));
+    }
+}
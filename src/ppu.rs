@@ -0,0 +1,1318 @@
+use alloc::rc::Rc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use bitflags::bitflags;
+
+use crate::cartridge::{Cartridge, Mirroring, Timing};
+use crate::nes::{Device, HEIGHT, WIDTH};
+
+bitflags! {
+    /// PPUCTRL, $2000. The nametable select bits live in `t`.
+    pub struct Ctrl: u8 {
+        const NAMETABLE         = 0b0000_0011;
+        const INCREMENT_32      = 0b0000_0100;
+        const SPRITE_TABLE      = 0b0000_1000;
+        const BACKGROUND_TABLE  = 0b0001_0000;
+        const TALL_SPRITES      = 0b0010_0000;
+        const MASTER_SLAVE      = 0b0100_0000;
+        const NMI               = 0b1000_0000;
+    }
+}
+
+bitflags! {
+    /// PPUMASK, $2001.
+    pub struct Mask: u8 {
+        const GREYSCALE         = 0b0000_0001;
+        const BACKGROUND_LEFT   = 0b0000_0010;
+        const SPRITES_LEFT      = 0b0000_0100;
+        const BACKGROUND        = 0b0000_1000;
+        const SPRITES           = 0b0001_0000;
+        const EMPHASIZE_RED     = 0b0010_0000;
+        const EMPHASIZE_GREEN   = 0b0100_0000;
+        const EMPHASIZE_BLUE    = 0b1000_0000;
+    }
+}
+
+bitflags! {
+    /// PPUSTATUS, $2002. The low five bits read back as PPU open bus.
+    pub struct Status: u8 {
+        const SPRITE_OVERFLOW   = 0b0010_0000;
+        const SPRITE_ZERO_HIT   = 0b0100_0000;
+        const VBLANK            = 0b1000_0000;
+    }
+}
+
+pub const OAM_SIZE: usize = 0x100;
+const PALETTE: u16 = 0x3f00;
+pub const DOTS_PER_LINE: u16 = 341;
+/// Sprites the PPU can show on one line.
+const LINE_SPRITES: usize = 8;
+
+/// How the PPU turns its memory into pixels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Renderer {
+    /// Draws a whole line at once. Fast, and right for games that only
+    /// change the scroll between lines.
+    #[default]
+    Scanline,
+    /// Runs the hardware's fetch pipeline dot by dot, so mid-line
+    /// register writes land where they would on a console.
+    Dot,
+}
+
+impl Renderer {
+    pub fn from_name(name: &str) -> Option<Renderer> {
+        match name {
+            "scanline" => Some(Renderer::Scanline),
+            "dot" => Some(Renderer::Dot),
+            _ => None,
+        }
+    }
+}
+
+/// The 2C02's CPU-facing side and its memory: 2K of nametable RAM (4K
+/// for four-screen boards), 32 bytes of palette RAM and 256 bytes of OAM.
+/// Pattern tables come from the cartridge.
+///
+/// PPUSCROLL and PPUADDR share the internal `t`, `x` and `w` registers
+/// the way the hardware does, so a game's scroll splits behave the same.
+/// Either [`Renderer`] draws the picture; the scanline one is the default.
+pub struct Ppu {
+    cartridge: Rc<RefCell<Cartridge>>,
+    renderer: Renderer,
+    ctrl: Ctrl,
+    mask: Mask,
+    status: Status,
+    oam_addr: u8,
+    oam: [u8; OAM_SIZE],
+    vram: [u8; 0x1000],
+    palette: [u8; 32],
+    /// The current VRAM address.
+    v: u16,
+    /// The address of the top-left onscreen tile, and PPUADDR's latch.
+    t: u16,
+    /// Fine X scroll.
+    x: u8,
+    /// The write toggle shared by PPUSCROLL and PPUADDR.
+    w: bool,
+    read_buffer: u8,
+    /// The PPU's own data bus, which write-only registers read back.
+    latch: u8,
+    nmi: bool,
+    /// Set by a PPUSTATUS read just before vblank starts, which keeps the
+    /// flag and the NMI from coming up this frame.
+    suppress_vblank: bool,
+    /// Position of the next dot to run; lines count from the first
+    /// visible one, the pre-render line being the last.
+    line: u16,
+    dot: u16,
+    frame: u64,
+    lines: u16,
+    vblank_line: u16,
+    /// Whether rendering shortens every other pre-render line by a dot.
+    skip_odd_dot: bool,
+    /// PPU dots per CPU cycle as a fraction, and the part of a dot left
+    /// over from the last tick.
+    dots_per_cycle: (u64, u64),
+    dot_carry: u64,
+    /// What the PPU drew: a palette entry in the low six bits and
+    /// PPUMASK's emphasis bits above them.
+    pixels: Vec<u16>,
+    frame_ready: bool,
+    /// The dot on this line a sprite 0 hit already drawn shows up in
+    /// PPUSTATUS.
+    sprite_zero_hit_dot: Option<u16>,
+    /// The dot renderer's background shift registers: pattern bits for
+    /// this tile and the next, and their palette bits spread to match.
+    pattern_lo: u16,
+    pattern_hi: u16,
+    attribute_lo: u16,
+    attribute_hi: u16,
+    /// Bytes fetched for the tile after those, loaded every 8 dots.
+    next_tile: u8,
+    next_attribute: u8,
+    next_lo: u8,
+    next_hi: u8,
+    /// Sprites found for the next line, and the search for them.
+    secondary_oam: [u8; LINE_SPRITES * 4],
+    evaluation: Evaluation,
+    /// Sprites fetched for the line being drawn.
+    sprites: [LineSprite; LINE_SPRITES],
+    sprite_count: usize,
+}
+
+/// Where the dot renderer's OAM scan is: sprite `n`, byte `m`.
+#[derive(Clone, Copy, Default)]
+struct Evaluation {
+    n: usize,
+    m: usize,
+    found: usize,
+    latch: u8,
+    zero: bool,
+    done: bool,
+}
+
+/// A sprite loaded for the dot renderer, its pattern already flipped so
+/// bit 7 is the leftmost pixel.
+#[derive(Clone, Copy, Default)]
+struct LineSprite {
+    x: u8,
+    attributes: u8,
+    lo: u8,
+    hi: u8,
+    zero: bool,
+}
+
+impl Ppu {
+    pub fn new(cartridge: Rc<RefCell<Cartridge>>) -> Ppu {
+        let (lines, vblank_line, skip_odd_dot, dots_per_cycle) = match cartridge.borrow().timing {
+            Timing::Ntsc | Timing::MultiRegion => (262, 241, true, (3, 1)),
+            Timing::Pal => (312, 241, false, (16, 5)),
+            // PAL's frame with NTSC's vblank length
+            Timing::Dendy => (312, 291, false, (3, 1)),
+        };
+        Ppu {
+            cartridge,
+            renderer: Renderer::default(),
+            ctrl: Ctrl::empty(),
+            mask: Mask::empty(),
+            status: Status::empty(),
+            oam_addr: 0,
+            oam: [0; OAM_SIZE],
+            vram: [0; 0x1000],
+            palette: [0; 32],
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+            read_buffer: 0,
+            latch: 0,
+            nmi: false,
+            suppress_vblank: false,
+            line: 0,
+            dot: 0,
+            frame: 0,
+            lines,
+            vblank_line,
+            skip_odd_dot,
+            dots_per_cycle,
+            dot_carry: 0,
+            pixels: vec![0; WIDTH * HEIGHT],
+            frame_ready: false,
+            sprite_zero_hit_dot: None,
+            pattern_lo: 0,
+            pattern_hi: 0,
+            attribute_lo: 0,
+            attribute_hi: 0,
+            next_tile: 0,
+            next_attribute: 0,
+            next_lo: 0,
+            next_hi: 0,
+            secondary_oam: [0xff; LINE_SPRITES * 4],
+            evaluation: Evaluation::default(),
+            sprites: [LineSprite::default(); LINE_SPRITES],
+            sprite_count: 0,
+        }
+    }
+
+    pub fn renderer(&self) -> Renderer {
+        self.renderer
+    }
+
+    /// Switches renderer. Takes effect from the next dot; a frame in
+    /// progress may show a seam.
+    pub fn set_renderer(&mut self, renderer: Renderer) {
+        self.renderer = renderer;
+    }
+
+    pub fn ctrl(&self) -> Ctrl {
+        self.ctrl
+    }
+
+    pub fn mask(&self) -> Mask {
+        self.mask
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    pub fn oam(&self) -> &[u8; OAM_SIZE] {
+        &self.oam
+    }
+
+    /// The last frame drawn, `WIDTH` × `HEIGHT` pixels.
+    pub fn pixels(&self) -> &[u16] {
+        &self.pixels
+    }
+
+    /// Whether a frame has been completed since the last call.
+    pub fn take_frame(&mut self) -> bool {
+        core::mem::take(&mut self.frame_ready)
+    }
+
+    /// Frames started since power-on.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// The line and dot about to be run.
+    pub fn position(&self) -> (u16, u16) {
+        (self.line, self.dot)
+    }
+
+    fn rendering(&self) -> bool {
+        self.mask.intersects(Mask::BACKGROUND | Mask::SPRITES)
+    }
+
+    /// Runs the dots `cycles` CPU cycles take.
+    pub fn run_cycles(&mut self, cycles: u64) {
+        let (num, den) = self.dots_per_cycle;
+        let dots = cycles * num + self.dot_carry;
+        self.dot_carry = dots % den;
+        for _ in 0..dots / den {
+            self.step();
+        }
+    }
+
+    /// Runs one dot.
+    fn step(&mut self) {
+        let pre_render = self.lines - 1;
+        if self.sprite_zero_hit_dot == Some(self.dot) {
+            self.sprite_zero_hit_dot = None;
+            self.status.insert(Status::SPRITE_ZERO_HIT);
+        }
+        match (self.line, self.dot) {
+            (line, 1) if line == self.vblank_line => {
+                if !core::mem::take(&mut self.suppress_vblank) {
+                    self.status.insert(Status::VBLANK);
+                    self.nmi |= self.ctrl.contains(Ctrl::NMI);
+                }
+                self.frame_ready = true;
+            }
+            (line, 1) if line == pre_render => {
+                self.status.remove(Status::VBLANK | Status::SPRITE_ZERO_HIT | Status::SPRITE_OVERFLOW);
+            }
+            _ => {}
+        }
+        match self.renderer {
+            Renderer::Scanline => self.step_scanline(),
+            Renderer::Dot => self.step_dot(),
+        }
+
+        self.dot += 1;
+        // the odd frames of an NTSC PPU that is rendering skip the
+        // pre-render line's last dot
+        if self.skip_odd_dot && self.line == pre_render && self.dot == DOTS_PER_LINE - 1
+            && self.frame % 2 == 1 && self.rendering()
+        {
+            self.dot = DOTS_PER_LINE;
+        }
+        if self.dot == DOTS_PER_LINE {
+            self.dot = 0;
+            self.line += 1;
+            if self.line == self.lines {
+                self.line = 0;
+                self.frame += 1;
+            }
+        }
+    }
+
+    /// The scanline renderer draws a whole line as the hardware starts
+    /// on it, then moves `v` on at dot 256 as the hardware would.
+    fn step_scanline(&mut self) {
+        let pre_render = self.lines - 1;
+        match (self.line, self.dot) {
+            (line, 1) if line < HEIGHT as u16 => self.render_line(line as usize),
+            (line, 256) if line < HEIGHT as u16 && self.rendering() => {
+                self.increment_y();
+                // the horizontal part of t is copied at dot 257
+                self.v = self.v & !0x041f | self.t & 0x041f;
+            }
+            (line, 304) if line == pre_render && self.rendering() => self.v = self.t,
+            _ => {}
+        }
+    }
+
+    /// The dot renderer follows the 2C02's timing diagram: a background
+    /// fetch every other dot, sprite evaluation for the next line
+    /// alongside, and sprite fetches in the horizontal blank.
+    fn step_dot(&mut self) {
+        let visible = self.line < HEIGHT as u16;
+        let pre_render = self.line == self.lines - 1;
+        let dot = self.dot;
+        if visible && dot == 65 {
+            // a line that turns rendering on after this must not fetch
+            // what was found on an earlier one
+            self.evaluation = Evaluation::default();
+        }
+        if !self.rendering() || !(visible || pre_render) {
+            if visible && (1..=256).contains(&dot) {
+                self.output_pixel();
+            }
+            return;
+        }
+
+        if (2..=257).contains(&dot) || (322..=337).contains(&dot) {
+            self.shift_background();
+        }
+        if (1..=256).contains(&dot) || (321..=336).contains(&dot) {
+            match (dot - 1) % 8 {
+                0 => {
+                    self.load_background();
+                    self.next_tile = self.vram_read(0x2000 | self.v & 0x0fff);
+                }
+                2 => self.next_attribute = self.attribute(self.v),
+                4 | 6 => {
+                    let table = if self.ctrl.contains(Ctrl::BACKGROUND_TABLE) { 0x1000 } else { 0 };
+                    let addr = table | (self.next_tile as u16) << 4 | self.v >> 12;
+                    if (dot - 1) % 8 == 4 {
+                        self.next_lo = self.vram_read(addr);
+                    } else {
+                        self.next_hi = self.vram_read(addr + 8);
+                    }
+                }
+                7 => self.v = increment_x(self.v),
+                _ => {}
+            }
+        }
+        match dot {
+            256 => self.increment_y(),
+            257 => {
+                self.load_background();
+                self.v = self.v & !0x041f | self.t & 0x041f;
+            }
+            280..=304 if pre_render => self.v = self.v & !0x7be0 | self.t & 0x7be0,
+            _ => {}
+        }
+
+        if visible {
+            match dot {
+                1..=64 if dot.is_multiple_of(2) => self.secondary_oam[dot as usize / 2 - 1] = 0xff,
+                65..=256 => self.evaluate_sprites(),
+                _ => {}
+            }
+        }
+        if (257..=320).contains(&dot) {
+            self.oam_addr = 0;
+            if pre_render {
+                // nothing is drawn on line 0 from what this line fetches
+                self.sprite_count = 0;
+            } else if (dot - 257) % 8 == 7 {
+                self.fetch_sprite((dot as usize - 257) / 8);
+            }
+        }
+        if visible && (1..=256).contains(&dot) {
+            self.output_pixel();
+        }
+    }
+
+    fn shift_background(&mut self) {
+        if self.mask.contains(Mask::BACKGROUND) {
+            self.pattern_lo <<= 1;
+            self.pattern_hi <<= 1;
+            self.attribute_lo <<= 1;
+            self.attribute_hi <<= 1;
+        }
+    }
+
+    fn load_background(&mut self) {
+        let spread = |bit: u8| if bit != 0 { 0xff } else { 0 };
+        self.pattern_lo = self.pattern_lo & 0xff00 | self.next_lo as u16;
+        self.pattern_hi = self.pattern_hi & 0xff00 | self.next_hi as u16;
+        self.attribute_lo = self.attribute_lo & 0xff00 | spread(self.next_attribute & 1);
+        self.attribute_hi = self.attribute_hi & 0xff00 | spread(self.next_attribute & 2);
+    }
+
+    /// One dot of the search through OAM for the next line's sprites:
+    /// odd dots read a byte, even dots act on it.
+    fn evaluate_sprites(&mut self) {
+        let mut eval = self.evaluation;
+        if eval.done {
+            return;
+        }
+        if self.dot % 2 == 1 {
+            self.evaluation.latch = self.oam[eval.n * 4 + eval.m];
+            return;
+        }
+        let in_range = self.sprite_in_range(self.line, eval.latch);
+        if eval.found < LINE_SPRITES {
+            self.secondary_oam[eval.found * 4 + eval.m] = eval.latch;
+            if eval.m != 0 {
+                eval.m = (eval.m + 1) % 4;
+                if eval.m == 0 {
+                    eval.found += 1;
+                    eval.n += 1;
+                }
+            } else if in_range {
+                eval.zero |= eval.n == 0;
+                eval.m = 1;
+            } else {
+                eval.n += 1;
+            }
+        } else if in_range {
+            self.status.insert(Status::SPRITE_OVERFLOW);
+            eval.done = true;
+        } else {
+            // the hardware moves on to the next sprite's next byte, so
+            // goes on to compare tiles, attributes and X with the line
+            eval.n += 1;
+            eval.m = (eval.m + 1) % 4;
+        }
+        eval.done |= eval.n == OAM_SIZE / 4;
+        self.evaluation = eval;
+    }
+
+    /// Loads sprite `i` of the next line from secondary OAM.
+    fn fetch_sprite(&mut self, i: usize) {
+        if i == 0 {
+            self.sprite_count = self.evaluation.found;
+        }
+        if i >= self.sprite_count {
+            return;
+        }
+        let sprite = &self.secondary_oam[i * 4..i * 4 + 4];
+        let (y, tile, attributes, x) = (sprite[0], sprite[1], sprite[2], sprite[3]);
+        let (mut lo, mut hi) = self.sprite_pattern(tile, attributes, self.line.wrapping_sub(y as u16) as u8);
+        if attributes & 0x40 != 0 {
+            lo = lo.reverse_bits();
+            hi = hi.reverse_bits();
+        }
+        self.sprites[i] = LineSprite { x, attributes, lo, hi, zero: i == 0 && self.evaluation.zero };
+    }
+
+    /// Draws the pixel for the current dot from the shift registers and
+    /// the line's sprites.
+    fn output_pixel(&mut self) {
+        let x = self.dot as usize - 1;
+        let y = self.line as usize;
+        if !self.rendering() {
+            let backdrop = self.palette_read(PALETTE);
+            self.pixels[y * WIDTH + x] = backdrop as u16 | (self.mask.bits() as u16 & 0xe0) << 1;
+            return;
+        }
+        let mut bg = 0;
+        if self.mask.contains(Mask::BACKGROUND) && (x >= 8 || self.mask.contains(Mask::BACKGROUND_LEFT)) {
+            let bit = 15 - self.x;
+            let pixel = (self.pattern_lo >> bit) as u8 & 1 | ((self.pattern_hi >> bit) as u8 & 1) << 1;
+            if pixel != 0 {
+                let palette = (self.attribute_lo >> bit) as u8 & 1 | ((self.attribute_hi >> bit) as u8 & 1) << 1;
+                bg = palette << 2 | pixel;
+            }
+        }
+        let mut sprite = None;
+        if self.mask.contains(Mask::SPRITES) && (x >= 8 || self.mask.contains(Mask::SPRITES_LEFT)) {
+            sprite = self.sprites[..self.sprite_count].iter().find_map(|sprite| {
+                let column = x.checked_sub(sprite.x as usize).filter(|&column| column < 8)?;
+                let bit = 7 - column;
+                let pixel = (sprite.lo >> bit) & 1 | ((sprite.hi >> bit) & 1) << 1;
+                (pixel != 0).then_some(SpritePixel {
+                    color: (sprite.attributes & 3) << 2 | pixel,
+                    behind: sprite.attributes & 0x20 != 0,
+                    zero: sprite.zero,
+                })
+            });
+        }
+        self.pixels[y * WIDTH + x] = self.compose(x, bg, sprite);
+    }
+
+    /// Moves `v` down a pixel, wrapping from row 29 into the nametable
+    /// below.
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let y = match (self.v & 0x03e0) >> 5 {
+            29 => {
+                self.v ^= 0x0800;
+                0
+            }
+            31 => 0,
+            y => y + 1,
+        };
+        self.v = self.v & !0x03e0 | y << 5;
+    }
+
+    fn render_line(&mut self, y: usize) {
+        let backdrop = self.palette_read(PALETTE);
+        let emphasis = (self.mask.bits() as u16 & 0xe0) << 1;
+        if !self.rendering() {
+            self.pixels[y * WIDTH..(y + 1) * WIDTH].fill(backdrop as u16 | emphasis);
+            return;
+        }
+        let background = self.background_line();
+        let sprites = self.sprite_line(y);
+        for x in 0..WIDTH {
+            self.pixels[y * WIDTH + x] = self.compose(x, background[x], sprites[x]);
+        }
+    }
+
+    /// Picks between the background and sprite pixels at `x`, where a
+    /// background of 0 is transparent, noting any sprite 0 hit. Clipped
+    /// pixels arrive transparent, so they never hit.
+    fn compose(&mut self, x: usize, bg: u8, sprite: Option<SpritePixel>) -> u16 {
+        let color = match sprite {
+            Some(sprite) => {
+                // the flag goes up at dot x + 2, from which the hardware
+                // draws pixel x; never at x = 255
+                if sprite.zero && bg != 0 && x != 255 && self.sprite_zero_hit_dot.is_none() {
+                    self.sprite_zero_hit_dot = Some(x as u16 + 2);
+                }
+                if sprite.behind && bg != 0 {
+                    self.palette_read(PALETTE | bg as u16)
+                } else {
+                    self.palette_read(PALETTE | 0x10 | sprite.color as u16)
+                }
+            }
+            None => self.palette_read(PALETTE | bg as u16),
+        };
+        color as u16 | (self.mask.bits() as u16 & 0xe0) << 1
+    }
+
+    /// The line's background as palette offsets, 0 where transparent.
+    fn background_line(&self) -> [u8; WIDTH] {
+        let mut line = [0; WIDTH];
+        if !self.mask.contains(Mask::BACKGROUND) {
+            return line;
+        }
+        let table = if self.ctrl.contains(Ctrl::BACKGROUND_TABLE) { 0x1000 } else { 0 };
+        let fine_y = self.v >> 12;
+        let mut v = self.v;
+        // 33 tiles cover the line whatever the fine X scroll
+        let mut x = -(self.x as isize);
+        for _ in 0..33 {
+            let tile = self.vram_read(0x2000 | v & 0x0fff) as u16;
+            let palette = self.attribute(v);
+            let addr = table | tile << 4 | fine_y;
+            let (lo, hi) = (self.vram_read(addr), self.vram_read(addr + 8));
+            for bit in (0..8).rev() {
+                let pixel = (lo >> bit) & 1 | ((hi >> bit) & 1) << 1;
+                if (0..WIDTH as isize).contains(&x) && pixel != 0 {
+                    line[x as usize] = palette << 2 | pixel;
+                }
+                x += 1;
+            }
+            v = increment_x(v);
+        }
+        if !self.mask.contains(Mask::BACKGROUND_LEFT) {
+            line[..8].fill(0);
+        }
+        line
+    }
+
+    /// The sprites on line `y`, front-most first where they overlap.
+    fn sprite_line(&mut self, y: usize) -> [Option<SpritePixel>; WIDTH] {
+        let mut line = [None; WIDTH];
+        if !self.mask.contains(Mask::SPRITES) {
+            return line;
+        }
+        // sprites are found on the line above the one they are drawn on,
+        // so none show on line 0
+        let Some(line_above) = (y as u16).checked_sub(1) else {
+            return line;
+        };
+        let (found, count, overflow) = self.evaluate_line(line_above);
+        if overflow {
+            self.status.insert(Status::SPRITE_OVERFLOW);
+        }
+        for &i in &found[..count] {
+            let sprite = &self.oam[i * 4..i * 4 + 4];
+            let (attributes, left) = (sprite[2], sprite[3] as usize);
+            let (lo, hi) = self.sprite_pattern(sprite[1], attributes, (line_above - sprite[0] as u16) as u8);
+            for column in 0..8 {
+                let x = left + column;
+                let bit = if attributes & 0x40 != 0 { column } else { 7 - column };
+                let pixel = (lo >> bit) & 1 | ((hi >> bit) & 1) << 1;
+                if x >= WIDTH || pixel == 0 || line[x].is_some() {
+                    continue;
+                }
+                if x < 8 && !self.mask.contains(Mask::SPRITES_LEFT) {
+                    continue;
+                }
+                line[x] = Some(SpritePixel {
+                    color: (attributes & 3) << 2 | pixel,
+                    behind: attributes & 0x20 != 0,
+                    zero: i == 0,
+                });
+            }
+        }
+        line
+    }
+
+    /// The scanline renderer's sprite evaluation on `line`: the first
+    /// eight sprites in range of the next line, and whether the overflow
+    /// flag goes up. The search for a ninth has the hardware's bug.
+    fn evaluate_line(&self, line: u16) -> ([usize; LINE_SPRITES], usize, bool) {
+        let mut found = [0; LINE_SPRITES];
+        let mut count = 0;
+        let mut n = 0;
+        while n < OAM_SIZE / 4 && count < LINE_SPRITES {
+            if self.sprite_in_range(line, self.oam[n * 4]) {
+                found[count] = n;
+                count += 1;
+            }
+            n += 1;
+        }
+        let mut m = 0;
+        while n < OAM_SIZE / 4 {
+            if self.sprite_in_range(line, self.oam[n * 4 + m]) {
+                return (found, count, true);
+            }
+            n += 1;
+            m = (m + 1) % 4;
+        }
+        (found, count, false)
+    }
+
+    /// Whether a sprite whose OAM Y is `y` shows on the line after
+    /// `line`.
+    fn sprite_in_range(&self, line: u16, y: u8) -> bool {
+        let height = if self.ctrl.contains(Ctrl::TALL_SPRITES) { 16 } else { 8 };
+        (0..height).contains(&(line as isize - y as isize))
+    }
+
+    /// The palette the attribute table gives the tile at `v`.
+    fn attribute(&self, v: u16) -> u8 {
+        let attribute = self.vram_read(0x23c0 | v & 0x0c00 | (v >> 4) & 0x38 | (v >> 2) & 0x07);
+        (attribute >> ((v >> 4) & 4 | v & 2)) & 3
+    }
+
+    /// The pattern bytes for `row` of a sprite, counted from its top
+    /// before any vertical flip.
+    fn sprite_pattern(&self, tile: u8, attributes: u8, row: u8) -> (u8, u8) {
+        let tall = self.ctrl.contains(Ctrl::TALL_SPRITES);
+        let height = if tall { 16 } else { 8 };
+        // the row is out of range if PPUCTRL changed the height since
+        // evaluation; the hardware only looks at its low bits
+        let row = row & (height - 1);
+        let row = if attributes & 0x80 != 0 { height - 1 - row } else { row } as u16;
+        let tile = tile as u16;
+        let addr = if tall {
+            (tile & 1) << 12 | (tile & 0xfe) << 4 | (row & 8) << 1 | row & 7
+        } else {
+            let table = if self.ctrl.contains(Ctrl::SPRITE_TABLE) { 0x1000 } else { 0 };
+            table | tile << 4 | row
+        };
+        (self.vram_read(addr), self.vram_read(addr + 8))
+    }
+
+    /// Reads PPU address space, $0000-$3FFF.
+    pub fn vram_read(&self, addr: u16) -> u8 {
+        let addr = addr & 0x3fff;
+        match addr {
+            0x0000..=0x1fff => self.cartridge.borrow().chr_read(addr),
+            0x2000..=0x3eff => self.vram[self.nametable_index(addr)],
+            _ => self.palette[palette_index(addr)],
+        }
+    }
+
+    pub fn vram_write(&mut self, addr: u16, val: u8) {
+        let addr = addr & 0x3fff;
+        match addr {
+            0x0000..=0x1fff => self.cartridge.borrow_mut().chr_write(addr, val),
+            0x2000..=0x3eff => self.vram[self.nametable_index(addr)] = val,
+            _ => self.palette[palette_index(addr)] = val & 0x3f,
+        }
+    }
+
+    /// Where `addr` lands in nametable RAM under the cartridge's mirroring.
+    fn nametable_index(&self, addr: u16) -> usize {
+        let offset = (addr as usize - 0x2000) % 0x1000;
+        let (table, offset) = (offset / 0x400, offset % 0x400);
+        let physical = match self.cartridge.borrow().mirroring {
+            Mirroring::Horizontal => table / 2,
+            Mirroring::Vertical => table % 2,
+            Mirroring::SingleLower => 0,
+            Mirroring::SingleUpper => 1,
+            Mirroring::FourScreen => table,
+        };
+        physical * 0x400 + offset
+    }
+
+    fn increment_v(&mut self) {
+        let step = if self.ctrl.contains(Ctrl::INCREMENT_32) { 32 } else { 1 };
+        self.v = self.v.wrapping_add(step) & 0x7fff;
+    }
+
+    /// A palette entry as the screen shows it, greyscale applied.
+    fn palette_read(&self, addr: u16) -> u8 {
+        let entry = self.palette[palette_index(addr)];
+        if self.mask.contains(Mask::GREYSCALE) {
+            entry & 0x30
+        } else {
+            entry
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SpritePixel {
+    /// Offset into the sprite palettes.
+    color: u8,
+    behind: bool,
+    zero: bool,
+}
+
+/// Moves `v` a tile right, wrapping into the nametable across.
+fn increment_x(v: u16) -> u16 {
+    if v & 0x001f == 31 {
+        v & !0x001f ^ 0x0400
+    } else {
+        v + 1
+    }
+}
+
+/// $3F10/$3F14/$3F18/$3F1C mirror the backdrop entries below them.
+fn palette_index(addr: u16) -> usize {
+    let index = addr as usize & 0x1f;
+    if index & 0x13 == 0x10 {
+        index & 0x0f
+    } else {
+        index
+    }
+}
+
+/// $2000-$2007, as decoded by the bus.
+impl Device for Ppu {
+    fn read(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            0x2002 => {
+                if self.line == self.vblank_line {
+                    match self.dot {
+                        // a read as the flag goes up sees it clear, and
+                        // the flag stays clear for the frame
+                        1 => self.suppress_vblank = true,
+                        // one just after it still cancels the NMI
+                        2 | 3 => self.nmi = false,
+                        _ => {}
+                    }
+                }
+                self.latch = self.status.bits() | self.latch & 0x1f;
+                self.status.remove(Status::VBLANK);
+                self.w = false;
+            }
+            0x2004 => {
+                let val = self.oam[self.oam_addr as usize];
+                // the attribute byte has no bits 2-4
+                self.latch = if self.oam_addr & 3 == 2 { val & 0xe3 } else { val };
+            }
+            0x2007 => {
+                let addr = self.v & 0x3fff;
+                if addr >= PALETTE {
+                    // the buffer gets the nametable byte underneath
+                    self.read_buffer = self.vram_read(addr - 0x1000);
+                    self.latch = self.palette_read(addr) | self.latch & 0xc0;
+                } else {
+                    self.latch = self.read_buffer;
+                    self.read_buffer = self.vram_read(addr);
+                }
+                self.increment_v();
+            }
+            _ => {}
+        }
+        Some(self.latch)
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.latch = val;
+        match addr {
+            0x2000 => {
+                let ctrl = Ctrl::from_bits_truncate(val);
+                // enabling NMI during vblank fires one straight away
+                if ctrl.contains(Ctrl::NMI) && !self.ctrl.contains(Ctrl::NMI) && self.status.contains(Status::VBLANK) {
+                    self.nmi = true;
+                }
+                self.ctrl = ctrl;
+                self.t = self.t & !0x0c00 | (val as u16 & 0x03) << 10;
+            }
+            0x2001 => self.mask = Mask::from_bits_truncate(val),
+            0x2003 => self.oam_addr = val,
+            0x2004 => {
+                self.oam[self.oam_addr as usize] = val;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+            0x2005 if !self.w => {
+                self.t = self.t & !0x001f | val as u16 >> 3;
+                self.x = val & 0x07;
+                self.w = true;
+            }
+            0x2005 => {
+                self.t = self.t & !0x73e0 | (val as u16 & 0x07) << 12 | (val as u16 & 0xf8) << 2;
+                self.w = false;
+            }
+            0x2006 if !self.w => {
+                self.t = self.t & 0x00ff | (val as u16 & 0x3f) << 8;
+                self.w = true;
+            }
+            0x2006 => {
+                self.t = self.t & 0xff00 | val as u16;
+                self.v = self.t;
+                self.w = false;
+            }
+            0x2007 => {
+                self.vram_write(self.v, val);
+                self.increment_v();
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.run_cycles(cycles);
+    }
+
+    fn take_nmi(&mut self) -> bool {
+        core::mem::take(&mut self.nmi)
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        Some(match addr {
+            0x2002 => self.status.bits() | self.latch & 0x1f,
+            0x2004 => self.oam[self.oam_addr as usize],
+            0x2007 => self.read_buffer,
+            _ => self.latch,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge;
+
+    fn ppu(mirroring: Mirroring) -> Ppu {
+        let mut cartridge = Cartridge::parse(&cartridge::ines(1, 0, 0, 0)).unwrap();
+        cartridge.mirroring = mirroring;
+        Ppu::new(Rc::new(RefCell::new(cartridge)))
+    }
+
+    fn set_addr(ppu: &mut Ppu, addr: u16) {
+        ppu.write(0x2006, (addr >> 8) as u8);
+        ppu.write(0x2006, addr as u8);
+    }
+
+    #[test]
+    fn test_status_read_clears_vblank_and_toggle() {
+        let mut ppu = ppu(Mirroring::Horizontal);
+        ppu.status.insert(Status::VBLANK | Status::SPRITE_ZERO_HIT);
+        ppu.write(0x2001, 0x1f);
+        assert_eq!(ppu.read(0x2002), Some(0xdf));
+        assert_eq!(ppu.read(0x2002), Some(0x5f));
+        // a half-written PPUADDR is forgotten
+        ppu.write(0x2006, 0x21);
+        ppu.status.insert(Status::VBLANK);
+        ppu.read(0x2002);
+        set_addr(&mut ppu, 0x2400);
+        assert_eq!(ppu.v, 0x2400);
+    }
+
+    #[test]
+    fn test_scroll_and_addr_share_t() {
+        let mut ppu = ppu(Mirroring::Horizontal);
+        ppu.write(0x2000, 0x03);
+        ppu.write(0x2005, 0x7d); // coarse X 15, fine X 5
+        ppu.write(0x2005, 0x5e); // coarse Y 11, fine Y 6
+        assert_eq!((ppu.t, ppu.x, ppu.w), (0x6d6f, 5, false));
+        ppu.write(0x2006, 0x04);
+        assert_eq!(ppu.t, 0x046f);
+        ppu.write(0x2006, 0x80);
+        assert_eq!((ppu.t, ppu.v), (0x0480, 0x0480));
+    }
+
+    #[test]
+    fn test_data_read_buffer_and_increment() {
+        let mut ppu = ppu(Mirroring::Vertical);
+        set_addr(&mut ppu, 0x2000);
+        for val in [1, 2, 3] {
+            ppu.write(0x2007, val);
+        }
+        set_addr(&mut ppu, 0x2000);
+        assert_eq!(ppu.read(0x2007), Some(0)); // stale buffer
+        assert_eq!(ppu.read(0x2007), Some(1));
+        assert_eq!(ppu.read(0x2007), Some(2));
+
+        ppu.write(0x2000, Ctrl::INCREMENT_32.bits());
+        set_addr(&mut ppu, 0x2000);
+        ppu.write(0x2007, 9);
+        assert_eq!(ppu.v, 0x2020);
+        // palette reads are immediate and buffer the nametable below
+        set_addr(&mut ppu, 0x3f01);
+        ppu.write(0x2007, 0x2a);
+        ppu.write(0x2000, 0);
+        set_addr(&mut ppu, 0x3f01);
+        assert_eq!(ppu.read(0x2007).map(|val| val & 0x3f), Some(0x2a));
+        assert_eq!(ppu.read_buffer, ppu.vram_read(0x2f01));
+    }
+
+    #[test]
+    fn test_palette_mirrors_and_greyscale() {
+        let mut ppu = ppu(Mirroring::Horizontal);
+        ppu.vram_write(0x3f10, 0x21);
+        ppu.vram_write(0x3f1d, 0xff);
+        assert_eq!(ppu.vram_read(0x3f00), 0x21);
+        assert_eq!(ppu.vram_read(0x3ff0), 0x21);
+        assert_eq!(ppu.vram_read(0x3f0d), 0x00);
+        assert_eq!(ppu.vram_read(0x3f1d), 0x3f);
+        ppu.write(0x2001, Mask::GREYSCALE.bits());
+        assert_eq!(ppu.palette_read(0x3f00), 0x20);
+    }
+
+    #[test]
+    fn test_nametable_mirroring() {
+        let cases = [
+            (Mirroring::Horizontal, [0, 0, 1, 1]),
+            (Mirroring::Vertical, [0, 1, 0, 1]),
+            (Mirroring::SingleLower, [0, 0, 0, 0]),
+            (Mirroring::SingleUpper, [1, 1, 1, 1]),
+            (Mirroring::FourScreen, [0, 1, 2, 3]),
+        ];
+        for (mirroring, tables) in cases {
+            let ppu = ppu(mirroring);
+            for (i, &table) in tables.iter().enumerate() {
+                let addr = 0x2000 + 0x400 * i as u16 + 5;
+                assert_eq!(ppu.nametable_index(addr), table * 0x400 + 5, "{:?}", mirroring);
+                assert_eq!(ppu.nametable_index(addr + 0x1000), table * 0x400 + 5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_oam_and_write_only_registers() {
+        let mut ppu = ppu(Mirroring::Horizontal);
+        ppu.write(0x2003, 0xfe);
+        for val in [0x11, 0x22, 0xff] {
+            ppu.write(0x2004, val);
+        }
+        assert_eq!((ppu.oam[0xfe], ppu.oam[0xff], ppu.oam[0x00]), (0x11, 0x22, 0xff));
+        ppu.write(0x2003, 0x02);
+        ppu.write(0x2004, 0xff);
+        ppu.write(0x2003, 0x02);
+        assert_eq!(ppu.read(0x2004), Some(0xe3));
+        assert_eq!(ppu.read(0x2000), Some(0xe3));
+
+        // CHR-RAM is writable through PPUDATA
+        set_addr(&mut ppu, 0x0010);
+        ppu.write(0x2007, 0x99);
+        assert_eq!(ppu.vram_read(0x0010), 0x99);
+    }
+
+    /// A PPU showing tile 1, solid colour 1, at the top-left of the
+    /// first nametable.
+    fn scene() -> Ppu {
+        let mut ppu = ppu(Mirroring::Horizontal);
+        for row in 0..8 {
+            ppu.vram_write(0x0010 + row, 0xff);
+        }
+        ppu.vram_write(0x2000, 1);
+        ppu.vram_write(0x3f00, 0x0f);
+        ppu.vram_write(0x3f01, 0x16);
+        ppu.vram_write(0x3f15, 0x2a);
+        // park every sprite below the screen
+        ppu.oam.fill(0xff);
+        ppu
+    }
+
+    fn run_frames(ppu: &mut Ppu, frames: u64) {
+        let target = ppu.frame + frames;
+        while ppu.frame < target {
+            ppu.run_cycles(1);
+        }
+    }
+
+    fn run_to_line(ppu: &mut Ppu, line: u16) {
+        while ppu.line != line {
+            ppu.run_cycles(1);
+        }
+    }
+
+    fn pixel(ppu: &Ppu, x: usize, y: usize) -> u16 {
+        ppu.pixels[y * WIDTH + x]
+    }
+
+    #[test]
+    fn test_vblank_and_nmi_timing() {
+        let mut ppu = ppu(Mirroring::Horizontal);
+        ppu.write(0x2000, Ctrl::NMI.bits());
+        // vblank starts on dot 1 of line 241
+        let dots = 241 * DOTS_PER_LINE as u64 + 1;
+        ppu.run_cycles(dots / 3);
+        assert_eq!(ppu.position(), (241, 1));
+        assert!(!ppu.status.contains(Status::VBLANK));
+        ppu.run_cycles(1);
+        assert!(ppu.status.contains(Status::VBLANK));
+        assert!(ppu.take_nmi() && !ppu.take_nmi());
+        assert!(ppu.take_frame());
+
+        // re-enabling NMI during vblank fires again
+        ppu.write(0x2000, 0);
+        ppu.write(0x2000, Ctrl::NMI.bits());
+        assert!(ppu.take_nmi());
+        run_frames(&mut ppu, 1);
+        assert!(!ppu.status.contains(Status::VBLANK));
+    }
+
+    #[test]
+    fn test_background_with_fine_scroll() {
+        let mut ppu = scene();
+        ppu.write(0x2001, (Mask::BACKGROUND | Mask::BACKGROUND_LEFT).bits());
+        run_frames(&mut ppu, 1);
+        assert_eq!(pixel(&ppu, 0, 0), 0x16);
+        assert_eq!(pixel(&ppu, 7, 7), 0x16);
+        assert_eq!(pixel(&ppu, 8, 0), 0x0f);
+        assert_eq!(pixel(&ppu, 0, 8), 0x0f);
+
+        // scroll written mid-frame lands on the next one
+        ppu.write(0x2005, 3);
+        ppu.write(0x2005, 2);
+        run_frames(&mut ppu, 2);
+        assert_eq!(pixel(&ppu, 4, 5), 0x16);
+        assert_eq!(pixel(&ppu, 5, 0), 0x0f);
+        assert_eq!(pixel(&ppu, 0, 6), 0x0f);
+
+        // emphasis bits ride along above the palette entry
+        ppu.write(0x2001, (Mask::BACKGROUND | Mask::EMPHASIZE_BLUE).bits());
+        run_frames(&mut ppu, 1);
+        assert_eq!(pixel(&ppu, 0, 0), 0x100 | 0x0f);
+    }
+
+    #[test]
+    fn test_sprites_flipping_priority_and_limit() {
+        let mut ppu = scene();
+        // a half-width sprite so flipping shows
+        for row in 0..8 {
+            ppu.vram_write(0x0020 + row, 0xf0);
+        }
+        ppu.write(0x2001, (Mask::SPRITES | Mask::BACKGROUND).bits());
+        ppu.oam[..8].copy_from_slice(&[9, 2, 0x01, 20, 9, 2, 0x41, 40]);
+        run_frames(&mut ppu, 1);
+        assert_eq!(pixel(&ppu, 20, 10), 0x2a);
+        assert_eq!(pixel(&ppu, 24, 10), 0x0f);
+        assert_eq!(pixel(&ppu, 20, 9), 0x0f);
+        assert_eq!(pixel(&ppu, 44, 17), 0x2a);
+        assert_eq!(pixel(&ppu, 43, 17), 0x0f);
+        run_to_line(&mut ppu, 241);
+        assert!(!ppu.status.contains(Status::SPRITE_OVERFLOW));
+
+        // behind an opaque background, in front of a transparent one
+        ppu.write(0x2001, (Mask::SPRITES | Mask::BACKGROUND | Mask::BACKGROUND_LEFT).bits());
+        ppu.oam[..4].copy_from_slice(&[0, 2, 0x21, 0]);
+        run_frames(&mut ppu, 1);
+        run_to_line(&mut ppu, 241);
+        assert_eq!(pixel(&ppu, 0, 1), 0x16);
+        for i in 2..11 {
+            ppu.oam[i * 4..i * 4 + 4].copy_from_slice(&[100, 2, 0, 0]);
+        }
+        run_frames(&mut ppu, 1);
+        run_to_line(&mut ppu, 241);
+        assert!(ppu.status.contains(Status::SPRITE_OVERFLOW));
+    }
+
+    #[test]
+    fn test_disabled_rendering_shows_the_backdrop() {
+        let mut ppu = scene();
+        run_frames(&mut ppu, 1);
+        assert!(ppu.pixels.iter().all(|&pixel| pixel == 0x0f));
+    }
+
+    fn frame_dots(ppu: &mut Ppu) -> u64 {
+        let frame = ppu.frame;
+        let mut dots = 0;
+        while ppu.frame == frame {
+            ppu.step();
+            dots += 1;
+        }
+        dots
+    }
+
+    fn run_to(ppu: &mut Ppu, line: u16, dot: u16) {
+        while ppu.position() != (line, dot) {
+            ppu.step();
+        }
+    }
+
+    #[test]
+    fn test_dot_renderer_matches_scanline() {
+        let frames: Vec<(Vec<u16>, Status)> = [Renderer::Scanline, Renderer::Dot].into_iter().map(|renderer| {
+            let mut ppu = scene();
+            ppu.set_renderer(renderer);
+            for row in 0..8 {
+                ppu.vram_write(0x0020 + row, 0xf0);
+                ppu.vram_write(0x0038 + row, 0x3c);
+            }
+            // a second tile, on another palette, wrapping across nametables
+            ppu.vram_write(0x201f, 3);
+            ppu.vram_write(0x2400, 1);
+            ppu.vram_write(0x23c7, 0x02);
+            ppu.vram_write(0x3f0b, 0x21);
+            ppu.oam[..12].copy_from_slice(&[0, 2, 0x20, 2, 9, 2, 0xc1, 250, 30, 3, 0x02, 100]);
+            for i in 3..12 {
+                ppu.oam[i * 4..i * 4 + 4].copy_from_slice(&[60, 3, 0, i as u8 * 9]);
+            }
+            ppu.write(0x2001, (Mask::all() - Mask::GREYSCALE - Mask::EMPHASIZE_RED).bits());
+            ppu.write(0x2005, 5);
+            ppu.write(0x2005, 0);
+            run_frames(&mut ppu, 1);
+            run_to_line(&mut ppu, 241);
+            (ppu.pixels.clone(), ppu.status)
+        }).collect();
+        assert!(frames[0].0 == frames[1].0);
+        // the flipped sprite's right edge
+        assert_eq!(frames[1].0[10 * WIDTH + 255] & 0x3f, 0x2a);
+        assert_eq!(frames[0].1, frames[1].1);
+        assert!(frames[1].1.contains(Status::SPRITE_OVERFLOW));
+    }
+
+    #[test]
+    fn test_dot_renderer_sees_mid_line_writes() {
+        let mut ppu = scene();
+        ppu.set_renderer(Renderer::Dot);
+        for column in 0..32 {
+            ppu.vram_write(0x2000 + column, 1);
+        }
+        ppu.write(0x2001, (Mask::BACKGROUND | Mask::BACKGROUND_LEFT).bits());
+        run_frames(&mut ppu, 1);
+        run_to(&mut ppu, 4, 101);
+        ppu.write(0x2001, 0);
+        run_to_line(&mut ppu, 241);
+        assert_eq!(pixel(&ppu, 99, 4), 0x16);
+        assert_eq!(pixel(&ppu, 100, 4), 0x0f);
+        assert_eq!(pixel(&ppu, 0, 5), 0x0f);
+        assert_eq!(pixel(&ppu, 0, 3), 0x16);
+    }
+
+    #[test]
+    fn test_dot_renderer_survives_rendering_off_mid_frame() {
+        let mut ppu = scene();
+        ppu.set_renderer(Renderer::Dot);
+        ppu.oam[..4].copy_from_slice(&[200, 1, 1, 128]);
+        ppu.write(0x2001, (Mask::SPRITES | Mask::SPRITES_LEFT).bits());
+        run_frames(&mut ppu, 1);
+        // off after line 200 found the sprite, back on mid-evaluation
+        run_to(&mut ppu, 201, 0);
+        ppu.write(0x2001, 0);
+        run_frames(&mut ppu, 1);
+        run_to(&mut ppu, 50, 100);
+        ppu.write(0x2001, (Mask::SPRITES | Mask::SPRITES_LEFT).bits());
+        run_to_line(&mut ppu, 241);
+        assert_eq!(pixel(&ppu, 128, 51), 0x0f);
+        run_frames(&mut ppu, 1);
+        run_to_line(&mut ppu, 241);
+        assert_eq!(pixel(&ppu, 128, 201), 0x2a);
+    }
+
+    #[test]
+    fn test_odd_frames_skip_a_dot_while_rendering() {
+        let mut ppu = scene();
+        assert_eq!(frame_dots(&mut ppu), 262 * 341);
+        assert_eq!(frame_dots(&mut ppu), 262 * 341);
+        ppu.write(0x2001, Mask::BACKGROUND.bits());
+        assert_eq!(frame_dots(&mut ppu), 262 * 341);
+        assert_eq!(frame_dots(&mut ppu), 262 * 341 - 1);
+    }
+
+    #[test]
+    fn test_status_read_races_vblank() {
+        let mut ppu = ppu(Mirroring::Horizontal);
+        ppu.write(0x2000, Ctrl::NMI.bits());
+        run_to(&mut ppu, 241, 1);
+        assert_eq!(ppu.read(0x2002).unwrap() & 0x80, 0);
+        run_to(&mut ppu, 241, 10);
+        assert!(!ppu.status.contains(Status::VBLANK));
+        assert!(!ppu.take_nmi());
+
+        run_frames(&mut ppu, 1);
+        run_to(&mut ppu, 241, 2);
+        assert_eq!(ppu.read(0x2002).unwrap() & 0x80, 0x80);
+        assert!(!ppu.take_nmi());
+
+        run_frames(&mut ppu, 1);
+        run_to(&mut ppu, 241, 4);
+        assert!(ppu.take_nmi());
+    }
+
+    /// Where the sprite 0 hit flag first goes up in a frame with `sprite`
+    /// as sprite 0 over an opaque background, in the manner of blargg's
+    /// sprite_hit_tests: the line, and the dot that raised it.
+    fn first_hit(renderer: Renderer, sprite: [u8; 4], mask: Mask) -> Option<(u16, u16)> {
+        let mut ppu = scene();
+        ppu.set_renderer(renderer);
+        for addr in 0x2000..0x23c0 {
+            ppu.vram_write(addr, 1);
+        }
+        // tile 2 is opaque in its left half only
+        for row in 0..8 {
+            ppu.vram_write(0x0020 + row, 0xf0);
+        }
+        ppu.oam[..4].copy_from_slice(&sprite);
+        ppu.write(0x2001, mask.bits());
+        run_frames(&mut ppu, 1);
+        while ppu.line < HEIGHT as u16 {
+            let position = ppu.position();
+            ppu.step();
+            if ppu.status.contains(Status::SPRITE_ZERO_HIT) {
+                return Some(position);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn test_sprite_zero_hit() {
+        let all = Mask::BACKGROUND | Mask::SPRITES | Mask::BACKGROUND_LEFT | Mask::SPRITES_LEFT;
+        for renderer in [Renderer::Scanline, Renderer::Dot] {
+            let hit = |sprite, mask| first_hit(renderer, sprite, mask);
+            // the flag goes up two dots after the first overlapping pixel
+            assert_eq!(hit([100, 1, 0, 128], all), Some((101, 130)));
+            assert_eq!(hit([0, 1, 0, 0], all), Some((1, 2)));
+            // priority doesn't matter, transparency does
+            assert_eq!(hit([100, 1, 0x20, 128], all), Some((101, 130)));
+            assert_eq!(hit([100, 2, 0x40, 128], all), Some((101, 134)));
+            assert_eq!(hit([100, 0, 0, 128], all), None);
+            // never at x = 255
+            assert_eq!(hit([100, 1, 0, 255], all), None);
+            assert_eq!(hit([100, 1, 0, 254], all), Some((101, 256)));
+            // either left clip hides the first 8 pixels
+            assert_eq!(hit([100, 1, 0, 4], all - Mask::SPRITES_LEFT), Some((101, 10)));
+            assert_eq!(hit([100, 1, 0, 4], all - Mask::BACKGROUND_LEFT), Some((101, 10)));
+            assert_eq!(hit([100, 1, 0, 0], all - Mask::BACKGROUND_LEFT), None);
+            assert_eq!(hit([100, 1, 0, 1], all - Mask::SPRITES_LEFT), Some((101, 10)));
+            // both layers have to be on
+            assert_eq!(hit([100, 1, 0, 128], all - Mask::BACKGROUND), None);
+            assert_eq!(hit([100, 1, 0, 128], all - Mask::SPRITES), None);
+            // the bottom line hits, sprites below it don't show
+            assert_eq!(hit([238, 1, 0, 128], all), Some((239, 130)));
+            assert_eq!(hit([239, 1, 0, 128], all), None);
+        }
+    }
+
+    #[test]
+    fn test_buggy_sprite_overflow() {
+        let overflow = |renderer, extra: &[[u8; 4]]| {
+            let mut ppu = scene();
+            ppu.set_renderer(renderer);
+            for i in 0..8 {
+                ppu.oam[i * 4..i * 4 + 4].copy_from_slice(&[100, 1, 0, 0]);
+            }
+            for (i, sprite) in extra.iter().enumerate() {
+                ppu.oam[32 + i * 4..36 + i * 4].copy_from_slice(sprite);
+            }
+            ppu.write(0x2001, Mask::SPRITES.bits());
+            run_frames(&mut ppu, 1);
+            run_to_line(&mut ppu, 241);
+            ppu.status.contains(Status::SPRITE_OVERFLOW)
+        };
+        for renderer in [Renderer::Scanline, Renderer::Dot] {
+            assert!(!overflow(renderer, &[]));
+            assert!(overflow(renderer, &[[104, 0xff, 0xff, 0xff]]));
+            // after a miss the scan reads the next sprite's tile as its Y
+            assert!(overflow(renderer, &[[0xff, 0xff, 0xff, 0xff], [0xff, 103, 0xff, 0xff]]));
+            // and so can step past a ninth sprite that is in range
+            assert!(!overflow(renderer, &[[0xff, 0xff, 0xff, 0xff], [103, 0xff, 0xff, 0xff]]));
+            assert!(overflow(renderer, &[[0xff; 4], [0xff; 4], [0xff, 0xff, 100, 0xff]]));
+        }
+    }
+}
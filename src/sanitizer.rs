@@ -0,0 +1,339 @@
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::symbols::SymbolTable;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Check {
+    UninitializedRead,
+    StackOverflow,
+    StackUnderflow,
+    ExecuteData,
+    ProgramWrite,
+    BadReturn,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Check::UninitializedRead => "read of never-written memory",
+            Check::StackOverflow => "stack overflow",
+            Check::StackUnderflow => "stack underflow",
+            Check::ExecuteData => "execution of bytes written as data",
+            Check::ProgramWrite => "write into loaded program",
+            Check::BadReturn => "RTS to an address not pushed by JSR",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Off,
+    Warning,
+    Error,
+}
+
+/// Severity of each check. Everything is a warning by default; CI runs
+/// usually want `SanitizerConfig::strict()`.
+#[derive(Debug, Clone, Copy)]
+pub struct SanitizerConfig {
+    pub uninitialized_read: Severity,
+    pub stack: Severity,
+    pub execute_data: Severity,
+    pub program_write: Severity,
+    pub bad_return: Severity,
+}
+
+impl Default for SanitizerConfig {
+    fn default() -> Self {
+        SanitizerConfig {
+            uninitialized_read: Severity::Warning,
+            stack: Severity::Warning,
+            execute_data: Severity::Warning,
+            program_write: Severity::Warning,
+            bad_return: Severity::Warning,
+        }
+    }
+}
+
+impl SanitizerConfig {
+    pub fn strict() -> Self {
+        SanitizerConfig {
+            uninitialized_read: Severity::Error,
+            stack: Severity::Error,
+            execute_data: Severity::Error,
+            program_write: Severity::Error,
+            bad_return: Severity::Error,
+        }
+    }
+
+    fn severity(&self, check: Check) -> Severity {
+        match check {
+            Check::UninitializedRead => self.uninitialized_read,
+            Check::StackOverflow | Check::StackUnderflow => self.stack,
+            Check::ExecuteData => self.execute_data,
+            Check::ProgramWrite => self.program_write,
+            Check::BadReturn => self.bad_return,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub check: Check,
+    pub severity: Severity,
+    /// Address of the instruction that triggered the finding.
+    pub pc: u16,
+    /// Memory address involved (read/write target, stack slot or return address).
+    pub addr: u16,
+    /// JSR call sites, innermost first.
+    pub backtrace: Vec<u16>,
+}
+
+impl Finding {
+    /// Formats the finding with addresses and backtrace resolved to labels.
+    pub fn display<'a>(&'a self, symbols: Option<&'a SymbolTable>) -> FindingDisplay<'a> {
+        FindingDisplay { finding: self, symbols }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.display(None).fmt(f)
+    }
+}
+
+pub struct FindingDisplay<'a> {
+    finding: &'a Finding,
+    symbols: Option<&'a SymbolTable>,
+}
+
+impl fmt::Display for FindingDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let finding = self.finding;
+        let describe = |addr: u16| match self.symbols {
+            Some(symbols) => symbols.describe(addr, None),
+            None => format!("${:04x}", addr),
+        };
+        let level = if finding.severity == Severity::Error { "error" } else { "warning" };
+        writeln!(
+            f,
+            "{}: {} at {} (pc {})",
+            level,
+            finding.check,
+            describe(finding.addr),
+            describe(finding.pc)
+        )?;
+        writeln!(f, "  #0 {}", describe(finding.pc))?;
+        for (i, site) in finding.backtrace.iter().enumerate() {
+            writeln!(f, "  #{} {}", i + 1, describe(*site))?;
+        }
+        Ok(())
+    }
+}
+
+struct Frame {
+    site: u16,
+    ret: u16,
+}
+
+/// Opt-in checker fed by `CPU` on every memory access, stack operation,
+/// instruction fetch and subroutine call.
+pub struct Sanitizer {
+    config: SanitizerConfig,
+    written: Vec<bool>,
+    data: Vec<bool>,
+    program: Vec<(u16, u16)>,
+    calls: Vec<Frame>,
+    pc: u16,
+    seen: BTreeSet<(Check, u16, u16)>,
+    findings: Vec<Finding>,
+}
+
+impl Sanitizer {
+    pub fn new(config: SanitizerConfig) -> Sanitizer {
+        Sanitizer {
+            config,
+            written: vec![false; 0x10000],
+            data: vec![false; 0x10000],
+            program: Vec::new(),
+            calls: Vec::new(),
+            pc: 0,
+            seen: BTreeSet::new(),
+            findings: Vec::new(),
+        }
+    }
+
+    pub fn findings(&self) -> &[Finding] {
+        &self.findings
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.findings.iter().any(|f| f.severity == Severity::Error)
+    }
+
+    /// Current JSR call sites, innermost first.
+    pub fn backtrace(&self) -> Vec<u16> {
+        self.calls.iter().rev().map(|frame| frame.site).collect()
+    }
+
+    fn report(&mut self, check: Check, addr: u16) {
+        let severity = self.config.severity(check);
+        if severity == Severity::Off || !self.seen.insert((check, self.pc, addr)) {
+            return;
+        }
+        self.findings.push(Finding {
+            check,
+            severity,
+            pc: self.pc,
+            addr,
+            backtrace: self.backtrace(),
+        });
+    }
+
+    pub(crate) fn on_load(&mut self, start: u16, len: usize) {
+        let end = start as usize + len;
+        for addr in start as usize..end {
+            self.written[addr] = true;
+            self.data[addr] = false;
+        }
+        if len > 0 {
+            self.program.push((start, (end - 1) as u16));
+        }
+    }
+
+    /// Called before an instruction of `len` bytes at `pc` runs; operands
+    /// written as data count as much as the opcode.
+    pub(crate) fn on_fetch(&mut self, pc: u16, len: u8) {
+        self.pc = pc;
+        for offset in 0..len as u16 {
+            let addr = pc.wrapping_add(offset);
+            if self.data[addr as usize] {
+                self.report(Check::ExecuteData, addr);
+            }
+        }
+    }
+
+    pub(crate) fn on_read(&mut self, addr: u16) {
+        if !self.written[addr as usize] {
+            self.report(Check::UninitializedRead, addr);
+        }
+    }
+
+    pub(crate) fn on_write(&mut self, addr: u16) {
+        self.written[addr as usize] = true;
+        self.data[addr as usize] = true;
+        if self.program.iter().any(|&(start, end)| (start..=end).contains(&addr)) {
+            self.report(Check::ProgramWrite, addr);
+        }
+    }
+
+    pub(crate) fn on_push(&mut self, sp: u8, slot: u16) {
+        if sp == 0x00 {
+            self.report(Check::StackOverflow, slot);
+        }
+    }
+
+    pub(crate) fn on_pop(&mut self, sp: u8, slot: u16) {
+        if sp == 0xff {
+            self.report(Check::StackUnderflow, slot);
+        }
+    }
+
+    pub(crate) fn on_call(&mut self, site: u16, ret: u16) {
+        self.calls.push(Frame { site, ret });
+    }
+
+    pub(crate) fn on_return(&mut self, ret: u16) {
+        match self.calls.iter().rposition(|frame| frame.ret == ret) {
+            // returning past inner frames unwinds them, like a longjmp
+            Some(depth) => self.calls.truncate(depth),
+            None => self.report(Check::BadReturn, ret),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::CPU;
+
+    fn run(program: Vec<u8>) -> CPU {
+        let mut cpu = CPU::new();
+        cpu.enable_sanitizer(SanitizerConfig::default());
+        cpu.load_and_run(program);
+        cpu
+    }
+
+    fn checks(cpu: &CPU) -> Vec<Check> {
+        cpu.sanitizer().unwrap().findings().iter().map(|f| f.check).collect()
+    }
+
+    #[test]
+    fn test_uninitialized_read() {
+        let cpu = run(vec![0xa5, 0x10, 0x85, 0x11, 0xa5, 0x11, 0x00]);
+        let findings = cpu.sanitizer().unwrap().findings();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].check, Check::UninitializedRead);
+        assert_eq!(findings[0].addr, 0x10);
+        assert_eq!(findings[0].pc, 0x0600);
+    }
+
+    #[test]
+    fn test_program_write_and_execute_data() {
+        // STA $0605 patches the BRK that follows it into a NOP
+        let cpu = run(vec![0xa9, 0xea, 0x8d, 0x05, 0x06, 0x00, 0x00]);
+        assert_eq!(checks(&cpu), vec![Check::ProgramWrite, Check::ExecuteData]);
+    }
+
+    #[test]
+    fn test_execute_data_in_operand() {
+        // STA $0606 patches the operand of the LDX that follows it
+        let cpu = run(vec![0xa9, 0x05, 0x8d, 0x06, 0x06, 0xa2, 0x00, 0x00]);
+        let findings = cpu.sanitizer().unwrap().findings();
+        let execute = findings.iter().find(|f| f.check == Check::ExecuteData).unwrap();
+        assert_eq!((execute.pc, execute.addr), (0x0605, 0x0606));
+        assert_eq!(cpu.rx, 0x05);
+    }
+
+    #[test]
+    fn test_bad_return_has_backtrace() {
+        // JSR $0604; BRK; PLA; PLA; RTS
+        let cpu = run(vec![0x20, 0x04, 0x06, 0x00, 0x68, 0x68, 0x60]);
+        let findings = cpu.sanitizer().unwrap().findings();
+        let bad = findings.iter().find(|f| f.check == Check::BadReturn).unwrap();
+        assert_eq!(bad.pc, 0x0606);
+        assert_eq!(bad.backtrace, vec![0x0600]);
+    }
+
+    #[test]
+    fn test_stack_underflow_is_fatal_when_strict() {
+        let mut cpu = CPU::new();
+        cpu.enable_sanitizer(SanitizerConfig {
+            uninitialized_read: Severity::Off,
+            ..SanitizerConfig::strict()
+        });
+        // PLA x3 pops past $01ff; the BRK is never reached
+        cpu.load_and_run(vec![0x68, 0x68, 0x68, 0xe8, 0x00]);
+        assert!(cpu.sanitizer().unwrap().has_errors());
+        assert!(checks(&cpu).contains(&Check::StackUnderflow));
+        assert_eq!(cpu.rx, 0);
+    }
+
+    #[test]
+    fn test_backtrace_uses_symbols() {
+        let cpu = run(vec![0x20, 0x04, 0x06, 0x00, 0xa5, 0x10, 0x60]);
+        let mut symbols = SymbolTable::new();
+        symbols.insert("main", 0x0600, None);
+        symbols.insert("update", 0x0604, None);
+        let finding = &cpu.sanitizer().unwrap().findings()[0];
+        assert_eq!(
+            finding.display(Some(&symbols)).to_string(),
+            "warning: read of never-written memory at $0010 (pc update)\n  #0 update\n  #1 main\n"
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use alloc::boxed::Box;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+/// A CPU clock and the display rate it is presented at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Clock {
+    pub hz: f64,
+    pub frame_rate: f64,
+}
+
+impl Clock {
+    pub const NTSC: Clock = Clock { hz: 1_789_773.0, frame_rate: 60.0988 };
+    pub const PAL: Clock = Clock { hz: 1_662_607.0, frame_rate: 50.0070 };
+    pub const DENDY: Clock = Clock { hz: 1_773_448.0, frame_rate: 50.0070 };
+    pub const EASY6502: Clock = Clock { hz: 1_000_000.0, frame_rate: 60.0 };
+
+    pub fn from_name(name: &str) -> Option<Clock> {
+        match name {
+            "ntsc" => Some(Clock::NTSC),
+            "pal" => Some(Clock::PAL),
+            "dendy" => Some(Clock::DENDY),
+            "easy6502" => Some(Clock::EASY6502),
+            _ => None,
+        }
+    }
+
+    pub fn cycles_per_frame(&self) -> f64 {
+        self.hz / self.frame_rate
+    }
+}
+
+/// The host clock the scheduler paces against.
+pub trait Timer {
+    /// Time elapsed since some fixed origin.
+    fn now(&mut self) -> Duration;
+    fn sleep(&mut self, duration: Duration);
+}
+
+/// The monotonic system clock.
+#[cfg(feature = "std")]
+pub struct StdTimer {
+    origin: Instant,
+}
+
+#[cfg(feature = "std")]
+impl Default for StdTimer {
+    fn default() -> Self {
+        StdTimer { origin: Instant::now() }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Timer for StdTimer {
+    fn now(&mut self) -> Duration {
+        self.origin.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// How far behind the host clock emulation may fall before the scheduler
+/// gives up catching up, e.g. after the process was suspended.
+const MAX_LAG: Duration = Duration::from_millis(250);
+
+/// Paces emulation against the host clock in frame-sized batches. The
+/// emulator runs `frame_budget()` cycles, presents, then calls `sync()`,
+/// which sleeps until the host has caught up with the emulated time.
+pub struct Scheduler {
+    clock: Clock,
+    timer: Box<dyn Timer>,
+    throttled: bool,
+    speed: f64,
+    carry: f64,
+    start: Duration,
+    emulated: Duration,
+    paused: bool,
+    advance: bool,
+}
+
+impl Scheduler {
+    #[cfg(feature = "std")]
+    pub fn new(clock: Clock) -> Scheduler {
+        Scheduler::with_timer(clock, Box::<StdTimer>::default())
+    }
+
+    pub fn with_timer(clock: Clock, mut timer: Box<dyn Timer>) -> Scheduler {
+        let start = timer.now();
+        Scheduler {
+            clock,
+            timer,
+            throttled: true,
+            speed: 1.0,
+            carry: 0.0,
+            start,
+            emulated: Duration::ZERO,
+            paused: false,
+            advance: false,
+        }
+    }
+
+    /// Runs as fast as the host allows, still in frame-sized batches.
+    #[cfg(feature = "std")]
+    pub fn unthrottled(clock: Clock) -> Scheduler {
+        Scheduler::new(clock).without_throttle()
+    }
+
+    pub fn without_throttle(self) -> Scheduler {
+        Scheduler { throttled: false, ..self }
+    }
+
+    /// Scales emulated time against host time: 2.0 runs twice as fast.
+    pub fn set_speed(&mut self, speed: f64) {
+        assert!(speed > 0.0, "speed must be positive");
+        let now = self.timer.now();
+        self.resync(now);
+        self.speed = speed;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        let now = self.timer.now();
+        self.resync(now);
+    }
+
+    /// While paused, lets exactly one more frame run.
+    pub fn advance_frame(&mut self) {
+        self.advance = self.paused;
+    }
+
+    /// Whether the next frame should be emulated; consumes a pending
+    /// frame advance.
+    pub fn should_run(&mut self) -> bool {
+        !self.paused || core::mem::take(&mut self.advance)
+    }
+
+    /// Cycles to run for the next frame. The fractional part of the
+    /// clock's cycles per frame is carried over so none are lost.
+    pub fn frame_budget(&mut self) -> u64 {
+        let budget = self.clock.cycles_per_frame() + self.carry;
+        let whole = budget as u64;
+        self.carry = budget - whole as f64;
+        whole
+    }
+
+    /// Accounts for one emulated frame and sleeps until the host clock has
+    /// caught up with it.
+    pub fn sync(&mut self) {
+        let now = self.timer.now();
+        if let Some(delay) = self.frame_done(now) {
+            self.timer.sleep(delay);
+        }
+    }
+
+    /// How long to wait at `now` once a frame has been emulated.
+    fn frame_done(&mut self, now: Duration) -> Option<Duration> {
+        if !self.throttled || self.paused {
+            // pacing resumes from the moment these end
+            self.resync(now);
+            return self.paused.then(|| self.frame_time());
+        }
+        self.emulated += self.frame_time();
+        let deadline = self.start + self.emulated;
+        if deadline > now {
+            Some(deadline - now)
+        } else {
+            if now - deadline > MAX_LAG {
+                self.resync(now);
+            }
+            None
+        }
+    }
+
+    fn frame_time(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / (self.clock.frame_rate * self.speed))
+    }
+
+    fn resync(&mut self, now: Duration) {
+        self.start = now;
+        self.emulated = Duration::ZERO;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct FakeTimer;
+
+    impl Timer for FakeTimer {
+        fn now(&mut self) -> Duration {
+            Duration::from_secs(100)
+        }
+
+        fn sleep(&mut self, _: Duration) {}
+    }
+
+    fn fake(clock: Clock) -> Scheduler {
+        Scheduler::with_timer(clock, Box::new(FakeTimer))
+    }
+
+    #[test]
+    fn test_frame_budget_carries_fractions() {
+        let mut scheduler = fake(Clock::NTSC);
+        let total: u64 = (0..60).map(|_| scheduler.frame_budget()).sum();
+        let expected = Clock::NTSC.cycles_per_frame() * 60.0;
+        assert!((total as f64 - expected).abs() < 1.0);
+        assert_eq!(fake(Clock::EASY6502).frame_budget(), 16_666);
+    }
+
+    #[test]
+    fn test_sync_paces_against_host_clock() {
+        let mut scheduler = fake(Clock::EASY6502);
+        let start = scheduler.start;
+        let frame = Duration::from_secs(1) / 60;
+        let delay = scheduler.frame_done(start).unwrap();
+        assert!(delay.abs_diff(frame) < Duration::from_micros(1));
+        // running late: no sleep
+        assert_eq!(scheduler.frame_done(start + frame * 3), None);
+
+        scheduler.set_speed(2.0);
+        let start = scheduler.start;
+        let delay = scheduler.frame_done(start).unwrap();
+        assert!(delay.abs_diff(frame / 2) < Duration::from_micros(1));
+
+        let mut fast = fake(Clock::EASY6502).without_throttle();
+        let start = fast.start;
+        assert_eq!(fast.frame_done(start), None);
+    }
+
+    #[test]
+    fn test_pause_and_frame_advance() {
+        let mut scheduler = fake(Clock::PAL);
+        assert!(scheduler.should_run());
+        scheduler.toggle_pause();
+        assert!(!scheduler.should_run());
+        scheduler.advance_frame();
+        assert!(scheduler.should_run());
+        assert!(!scheduler.should_run());
+        scheduler.toggle_pause();
+        scheduler.advance_frame();
+        assert!(scheduler.should_run());
+        assert!(scheduler.should_run());
+    }
+}
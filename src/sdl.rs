@@ -0,0 +1,122 @@
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::EventPump;
+
+use sens::keymap::Keymap;
+use sens::scheduler::Scheduler;
+use sens::symbols::SymbolTable;
+use sens::{StopReason, CPU};
+
+use crate::{emulate, Machine};
+
+/// Runs in an SDL window, paced by `scheduler`, until the program stops or
+/// the user quits.
+#[allow(clippy::too_many_arguments)]
+pub fn run_window(
+    cpu: &mut CPU,
+    machine: &dyn Machine,
+    title: &str,
+    scale: u32,
+    keymap: &Keymap,
+    key_up: bool,
+    mut scheduler: Scheduler,
+    trace: bool,
+    symbols: Option<&SymbolTable>,
+    reported: &mut usize,
+) -> StopReason {
+    // init sdl2
+    let (width, height) = {
+        let screen = machine.screen();
+        (screen.width() as u32, screen.height() as u32)
+    };
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+    let window = video_subsystem
+        .window(title, width * scale, height * scale)
+        .position_centered()
+        .build().unwrap();
+
+    let mut canvas = window.into_canvas().build().unwrap();
+    let mut event_pump = sdl_context.event_pump().unwrap();
+    video_subsystem.text_input().start();
+    canvas.set_scale(scale as f32, scale as f32).unwrap();
+
+    let creator = canvas.texture_creator();
+    let mut texture = creator
+        .create_texture_target(PixelFormatEnum::RGB24, width, height).unwrap();
+
+    // run the game cycle, one display frame at a time
+    let mut named = false;
+    loop {
+        if handle_user_input(machine, keymap, key_up, &mut named, &mut scheduler, &mut event_pump) {
+            break StopReason::Quit;
+        }
+        if !scheduler.should_run() {
+            scheduler.sync();
+            continue;
+        }
+        let reason = emulate(cpu, scheduler.frame_budget(), trace, symbols, reported);
+        if reason != StopReason::BudgetExhausted {
+            break reason;
+        }
+
+        let mut screen = machine.screen();
+        if let Some(rows) = screen.take_dirty_rows() {
+            let pitch = screen.pitch();
+            let rect = Rect::new(0, rows.start as i32, screen.width() as u32, rows.len() as u32);
+            texture.update(rect, &screen.pixels()[rows.start * pitch..rows.end * pitch], pitch).unwrap();
+            canvas.copy(&texture, None, None).unwrap();
+            canvas.present();
+        }
+        drop(screen);
+        scheduler.sync();
+    }
+}
+
+/// Feeds pending key events to the machine. Keys the keymap names are
+/// sent from their key codes, printable characters from the text they
+/// type, so Shift and the keyboard layout are honoured. `named` is whether
+/// the last key pressed was sent by name, so its text isn't sent too.
+/// Pause or F5 pauses and resumes, F6 advances one frame while paused.
+/// Returns true once the user has asked to quit.
+fn handle_user_input(
+    machine: &dyn Machine,
+    keymap: &Keymap,
+    key_up: bool,
+    named: &mut bool,
+    scheduler: &mut Scheduler,
+    event_pump: &mut EventPump,
+) -> bool {
+    for event in event_pump.poll_iter() {
+        match event {
+            Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => return true,
+            Event::KeyDown { keycode: Some(Keycode::Pause | Keycode::F5), repeat: false, .. } => {
+                scheduler.toggle_pause();
+            }
+            Event::KeyDown { keycode: Some(Keycode::F6), .. } => scheduler.advance_frame(),
+            Event::KeyDown { keycode: Some(keycode), .. } => {
+                let code = keymap.lookup(&keycode.name(), None);
+                *named = code.is_some();
+                if let Some(code) = code {
+                    machine.key_down(code);
+                }
+            }
+            Event::TextInput { text, .. } if !*named => {
+                for code in text.chars().filter_map(|c| keymap.lookup_text(c)) {
+                    machine.key_down(code);
+                }
+            }
+            Event::KeyUp { keycode: Some(keycode), .. } if key_up => {
+                match keymap.lookup(&keycode.name(), None) {
+                    Some(code) => machine.key_up(code),
+                    // the character it typed isn't known any more
+                    None => machine.release_keys(),
+                }
+            }
+            _ => {/* do nothing */}
+        }
+    }
+    false
+}
@@ -0,0 +1,263 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::{fs, io, path::Path};
+
+#[derive(Debug)]
+pub enum SymbolError {
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            SymbolError::Io(err) => write!(f, "{}", err),
+            SymbolError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for SymbolError {
+    fn from(err: io::Error) -> Self {
+        SymbolError::Io(err)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub addr: u16,
+    /// PRG bank the label lives in, `None` for labels visible in every bank.
+    pub bank: Option<u16>,
+}
+
+/// Labels merged from any number of assembler/debugger symbol files.
+#[derive(Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+    by_addr: BTreeMap<u16, Vec<usize>>,
+    by_name: BTreeMap<String, usize>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn insert(&mut self, name: &str, addr: u16, bank: Option<u16>) {
+        if let Some(&idx) = self.by_name.get(name) {
+            let old = &self.symbols[idx];
+            if old.addr == addr && old.bank == bank {
+                return;
+            }
+        }
+        let idx = self.symbols.len();
+        self.symbols.push(Symbol { name: name.to_string(), addr, bank });
+        self.by_addr.entry(addr).or_default().push(idx);
+        self.by_name.insert(name.to_string(), idx);
+    }
+
+    /// Loads a symbol file, picking the format from the extension:
+    /// `.dbg` is ca65 debug info, `.nl` an FCEUX name list and anything
+    /// else a VICE label file.
+    #[cfg(feature = "std")]
+    pub fn load(&mut self, path: &Path) -> Result<(), SymbolError> {
+        let text = fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("dbg") => self.parse_ca65(&text),
+            Some("nl") => self.parse_fceux(&text, fceux_bank(path)),
+            _ => self.parse_vice(&text),
+        }
+    }
+
+    /// Parses the `sym` records of a ca65 `--dbgfile`.
+    pub fn parse_ca65(&mut self, text: &str) -> Result<(), SymbolError> {
+        for (i, line) in text.lines().enumerate() {
+            let Some(record) = line.strip_prefix("sym\t") else {
+                continue;
+            };
+            let mut name = None;
+            let mut val = None;
+            let mut kind = None;
+            for field in record.split(',') {
+                match field.split_once('=') {
+                    Some(("name", v)) => name = Some(v.trim_matches('"')),
+                    Some(("val", v)) => val = Some(v),
+                    Some(("type", v)) => kind = Some(v),
+                    _ => {}
+                }
+            }
+            // equates without an address size are plain numbers, not labels
+            if kind != Some("lab") && !record.contains("addrsize=") {
+                continue;
+            }
+            let (Some(name), Some(val)) = (name, val) else {
+                continue;
+            };
+            let addr = parse_number(val).ok_or_else(|| parse_error(i, format!("bad value {:?}", val)))?;
+            if let Ok(addr) = u16::try_from(addr) {
+                self.insert(name, addr, None);
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses VICE monitor label files (`al C:0810 .main`), as written by
+    /// `ld65 -Ln`, and `name = $0810` listings.
+    pub fn parse_vice(&mut self, text: &str) -> Result<(), SymbolError> {
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+            let (name, addr) = if let Some(rest) = line.strip_prefix("al ") {
+                let mut parts = rest.split_whitespace();
+                let addr = parts.next().unwrap_or("");
+                let addr = addr.split_once(':').map_or(addr, |(_, a)| a);
+                let name = parts.next().unwrap_or("").trim_start_matches('.');
+                (name, u32::from_str_radix(addr, 16).ok())
+            } else if let Some((name, addr)) = line.split_once('=') {
+                (name.trim(), parse_number(addr.trim()))
+            } else {
+                return Err(parse_error(i, format!("unrecognised line {:?}", line)));
+            };
+            match addr.map(u16::try_from) {
+                Some(Ok(addr)) if !name.is_empty() => self.insert(name, addr, None),
+                _ => return Err(parse_error(i, format!("unrecognised line {:?}", line))),
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses an FCEUX name list (`$C000#Reset#comment`). `bank` is the PRG
+    /// bank the file describes, `None` for `.ram.nl`.
+    pub fn parse_fceux(&mut self, text: &str, bank: Option<u16>) -> Result<(), SymbolError> {
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut parts = line.splitn(3, '#');
+            let addr = parts.next().unwrap_or("");
+            let name = parts.next().unwrap_or("");
+            // "$0300/10" labels an array; only the base address matters here
+            let addr = addr.split('/').next().unwrap_or("");
+            let addr = addr
+                .strip_prefix('$')
+                .and_then(|a| u16::from_str_radix(a, 16).ok())
+                .ok_or_else(|| parse_error(i, format!("bad address in {:?}", line)))?;
+            if !name.is_empty() {
+                self.insert(name, addr, bank);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn resolve(&self, name: &str) -> Option<&Symbol> {
+        self.by_name.get(name).map(|&idx| &self.symbols[idx])
+    }
+
+    /// The label defined exactly at `addr`, preferring one in `bank`.
+    pub fn name_at(&self, addr: u16, bank: Option<u16>) -> Option<&str> {
+        let candidates = self.by_addr.get(&addr)?;
+        let visible = |idx: &&usize| {
+            let sym = &self.symbols[**idx];
+            sym.bank.is_none() || bank.is_none() || sym.bank == bank
+        };
+        candidates
+            .iter()
+            .filter(visible)
+            .max_by_key(|&&idx| self.symbols[idx].bank == bank)
+            .map(|&idx| self.symbols[idx].name.as_str())
+    }
+
+    /// `addr` as `label`, `label+offset` for the nearest preceding label
+    /// within 256 bytes, or `$xxxx`.
+    pub fn describe(&self, addr: u16, bank: Option<u16>) -> String {
+        let floor = addr.saturating_sub(0xff);
+        for (&base, _) in self.by_addr.range(floor..=addr).rev() {
+            if let Some(name) = self.name_at(base, bank) {
+                return if base == addr {
+                    name.to_string()
+                } else {
+                    format!("{}+{}", name, addr - base)
+                };
+            }
+        }
+        format!("${:04x}", addr)
+    }
+}
+
+fn parse_error(line: usize, message: String) -> SymbolError {
+    SymbolError::Parse { line: line + 1, message }
+}
+
+fn parse_number(text: &str) -> Option<u32> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix('$')) {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+/// FCEUX names per-bank lists `game.nes.<bank>.nl` and RAM lists
+/// `game.nes.ram.nl`.
+#[cfg(feature = "std")]
+fn fceux_bank(path: &Path) -> Option<u16> {
+    let stem = path.file_stem()?.to_str()?;
+    let (_, bank) = stem.rsplit_once('.')?;
+    u16::from_str_radix(bank, 16).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_all_formats() {
+        let mut symbols = SymbolTable::new();
+        symbols
+            .parse_ca65(concat!(
+                "version\tmajor=2,minor=0\n",
+                "sym\tid=0,name=\"reset\",addrsize=absolute,scope=0,def=1,val=0x8000,seg=0,type=lab\n",
+                "sym\tid=1,name=\"SPEED\",addrsize=zeropage,scope=0,def=2,val=0x4,type=equ\n",
+                "sym\tid=2,name=\"WIDTH\",scope=0,def=3,val=0x20,type=equ\n",
+            ))
+            .unwrap();
+        symbols.parse_vice("al C:0600 .init\nsnake_head = $10\n").unwrap();
+        symbols.parse_fceux("$C000#nmi#vblank handler\n$0300/10#buffer#\n", Some(3)).unwrap();
+
+        assert_eq!(symbols.symbols.len(), 6);
+        assert_eq!(symbols.resolve("reset").unwrap().addr, 0x8000);
+        assert!(symbols.resolve("WIDTH").is_none());
+        assert_eq!(symbols.name_at(0x0600, None), Some("init"));
+        assert_eq!(symbols.name_at(0xc000, Some(3)), Some("nmi"));
+        assert_eq!(symbols.name_at(0xc000, Some(2)), None);
+        assert_eq!(symbols.describe(0x0603, None), "init+3");
+        assert_eq!(symbols.describe(0x0004, None), "SPEED");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_fceux_bank_from_file_name() {
+        assert_eq!(fceux_bank(Path::new("game.nes.1f.nl")), Some(0x1f));
+        assert_eq!(fceux_bank(Path::new("game.nes.ram.nl")), None);
+    }
+
+    #[test]
+    fn test_parse_error_reports_line() {
+        let err = SymbolTable::new().parse_vice("al C:0600 .init\nnonsense\n").unwrap_err();
+        assert!(matches!(err, SymbolError::Parse { line: 2, .. }));
+    }
+}